@@ -0,0 +1,399 @@
+use errors::*;
+use failure::ResultExt;
+use destiny;
+use logging;
+use log::LogLevelFilter;
+use server;
+use state::AppConfig;
+use table;
+use term_size;
+use std::io::{self, Write};
+use std::path::Path;
+
+const USAGE: &str = "Usage: d2tools [command]
+
+Commands:
+  serve                 Run the web server (the default)
+  dev-cert [<dir>]      Write a self-signed localhost cert.pem and key.pem to <dir>
+  inventory [options]   Print every item on your characters and in the vault
+  perks                 Print the plugs in each item's sockets
+  max-power             Print each character's highest-power loadout and what to move
+  search <name|hash>    Look up item, plug and perk definitions in the cached manifest
+  progress              Print each character's faction ranks, progressions and milestones
+  pursuits [--incomplete]
+                        Print the objectives on bounties, quests, catalysts and trackers
+  collections [options] Print the exotics and legendaries each kiosk holds, and which are
+                        acquired or missing
+  vendors [<vendor>]    Print what vendors sell each character this week, with costs and
+                        rolled perks; <vendor> is a vendor hash or part of a name, e.g. Xur
+  history               Print the inventory snapshots taken so far
+  diff [<snapshot>]     Print what changed between the latest snapshot and <snapshot>,
+                        or the one before it
+  logout                Delete the API responses dumped for debugging. Bungie.net tokens
+                        aren't revoked; unset ACCESS_TOKEN and REFRESH_TOKEN yourself
+  purge [--yes]         Delete the dumped responses and the saved loadouts and
+                        inventory history, once confirmed (--yes to skip asking)
+  loadout list          Print the saved loadouts
+  loadout save <name>   Save what each character has equipped as <name>
+  loadout plan <name>   Print the transfers and equips that would restore <name>
+  loadout apply <name>  Show the plan, then make those moves once confirmed (--yes to skip asking)
+  loadout delete <name> Forget the loadout <name>
+
+Table commands also take:
+  --format <format>     One of text (the default), csv, tsv, markdown or jsonl
+
+Inventory options:
+  --columns a,b,c       Columns to show, e.g. name,tier,power,roll,character,impact
+  --stats               Add the weapon and armor stat columns
+  --sort a,-b           Sort keys, any column name; a leading - sorts descending
+  --group <column>      Group rows by a column, with counts and max power per group
+  --bucket <name>       Only items whose bucket name contains <name>
+  --tier <tier>         Only items of this tier, e.g. Exotic
+  --kind <kind>         Only items whose kind contains <kind>, e.g. \"Hand Cannon\"
+  --min-power <n>       Only items with at least this power
+  --locked              Only locked items
+  --character <id>      Only items held by this character id, or Vault
+  --rolls               Only items fully matching a wishlist roll
+  --json                Print whole items as JSON instead of a table
+  --stream              Print rows as items arrive, unsorted
+
+Collections options:
+  --source <source>     Only items whose kiosk or drop source contains <source>
+  --season <season>     Only items from this season number, or whose season name contains it
+  --missing             Only items not yet acquired
+
+The server listens on LISTEN_ADDR (127.0.0.1:8181 by default), serving HTTPS
+itself when TLS_CERT_PATH and TLS_KEY_PATH are set. It then relays to plain
+HTTP on a random 127.0.0.1 port, which another local user could grab as the
+server starts; on a shared host, use a TLS proxy in front of it instead.
+
+The inventory commands read ACCESS_TOKEN and API_KEY from the environment,
+and WISHLIST_PATH if set. Each inventory fetched from Bungie, --stream
+included, is kept as a snapshot of your account for history and diff. API
+responses are dumped to DEBUG_DUMP_DIR if it's set, keeping the newest
+DEBUG_DUMP_MAX_FILES (200) files, up to DEBUG_DUMP_MAX_BYTES (50MB) in all.
+With AUDIT_SCHEMA=1, the fields Bungie sends that aren't modeled, and modeled
+fields that came back missing, are reported on stderr after each command (and
+at /schema-report by the server).";
+
+pub fn run(args: Vec<String>) -> Result<()> {
+  let (command, rest) = match args.split_first() {
+    Some((command, rest)) => (command.as_str(), rest),
+    None => ("serve", &[][..]),
+  };
+
+  // The server sets up its own logging, to stdout.
+  if command != "serve" {
+    let cfg = AppConfig::from_env();
+    logging::configure(&cfg, LogLevelFilter::Warn, true)?;
+    cfg.apply_debug_options();
+  }
+
+  let result = match command {
+    "serve" => server::start_http(),
+    "dev-cert" => dev_cert(rest),
+    "inventory" => inventory(rest),
+    "perks" => perks(rest),
+    "max-power" => max_power(rest),
+    "search" => search(rest),
+    "vendors" => vendors(rest),
+    "progress" => progress(rest),
+    "pursuits" => pursuits(rest),
+    "collections" => collections(rest),
+    "loadout" => loadout(rest),
+    "history" => history(rest),
+    "diff" => diff(rest),
+    "logout" => logout(rest),
+    "purge" => purge(rest),
+    "help" | "-h" | "--help" => Ok(println!("{}", USAGE)),
+    other => Err(format_err!("Unknown command {:?}\n\n{}", other, USAGE)),
+  };
+
+  // Reported even if the command failed; drift is often why.
+  if let Some(report) = destiny::schema_report() {
+    eprintln!("\nSchema audit:\n{}", report.render(table::Format::Text));
+  }
+  result
+}
+
+fn credentials(cfg: &AppConfig) -> Result<(String, String)> {
+  if cfg.access_token.is_empty() || cfg.api_key.is_empty() {
+    bail!("ACCESS_TOKEN and API_KEY must be set")
+  }
+  Ok((cfg.access_token.clone(), cfg.api_key.clone()))
+}
+
+// Text tables are fitted to the terminal when we're writing to one.
+fn print_table<T>(mut table: table::Table<T>, format: table::Format) -> Result<()> {
+  if format == table::Format::Text {
+    if let Some((width, _)) = term_size::dimensions_stdout() {
+      table.fit_to_width(width);
+    }
+  }
+  Ok(print!("{}", table.render(format)))
+}
+
+// Pulls `--format <name>` out of the arguments, returning the rest.
+fn take_format(args: &[String]) -> Result<(table::Format, Vec<String>)> {
+  let mut format = table::Format::Text;
+  let mut rest = Vec::new();
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    if arg == "--format" {
+      let name = args.next().ok_or(format_err!("--format needs a value"))?;
+      format = table::Format::from_name(name).ok_or(format_err!("Unknown format {:?}", name))?;
+    } else {
+      rest.push(arg.clone());
+    }
+  }
+  Ok((format, rest))
+}
+
+fn dev_cert(args: &[String]) -> Result<()> {
+  let dir = match args.len() {
+    0 => ".",
+    1 => args[0].as_str(),
+    _ => bail!("dev-cert takes at most a directory\n\n{}", USAGE),
+  };
+  let (cert, key) = server::generate_dev_cert(Path::new(dir))?;
+  println!("Wrote {} and {}. Serve HTTPS with them by setting:", cert.display(), key.display());
+  println!("  TLS_CERT_PATH={}", cert.display());
+  Ok(println!("  TLS_KEY_PATH={}", key.display()))
+}
+
+fn inventory(args: &[String]) -> Result<()> {
+  let cfg = AppConfig::from_env();
+  let (token, api_key) = credentials(&cfg)?;
+  let (format, args) = take_format(args)?;
+
+  let mut opts = destiny::TableOptions::default();
+  opts.wishlist_path = cfg.wishlist();
+  let mut json = false;
+  let mut stream = false;
+
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    if !arg.starts_with("--") {
+      bail!("Unexpected argument {:?}\n\n{}", arg, USAGE)
+    }
+    let name = arg[2..].replace('-', "_");
+    if name == "json" {
+      json = true;
+      continue;
+    }
+    if name == "stream" {
+      stream = true;
+      continue;
+    }
+    let value = if destiny::TableOptions::takes_value(&name) {
+      args
+        .next()
+        .map(|v| v.as_str())
+        .ok_or(format_err!("{} needs a value", arg))?
+    } else {
+      ""
+    };
+    if !opts.set(&name, value)? {
+      bail!("Unknown option {}\n\n{}", arg, USAGE)
+    }
+  }
+
+  if json {
+    Ok(println!("{}", destiny::api_exchange_json(token, api_key, &opts)?))
+  } else if stream {
+    destiny::stream_inventory(token, api_key, &opts, format, |text| {
+      print!("{}", text);
+      Ok(io::stdout().flush()?)
+    })
+  } else {
+    print_table(destiny::api_exchange(token, api_key, &opts)?, format)
+  }
+}
+
+fn perks(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  if !args.is_empty() {
+    bail!("perks takes no other arguments\n\n{}", USAGE)
+  }
+  let (token, api_key) = credentials(&AppConfig::from_env())?;
+  print_table(destiny::perks(token, api_key)?, format)
+}
+
+fn max_power(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  if !args.is_empty() {
+    bail!("max-power takes no other arguments\n\n{}", USAGE)
+  }
+  let (token, api_key) = credentials(&AppConfig::from_env())?;
+  print_table(destiny::max_power(token, api_key)?, format)
+}
+
+fn vendors(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  let vendor = match args.len() {
+    0 => None,
+    1 => Some(args[0].as_str()),
+    _ => bail!("vendors takes at most a vendor\n\n{}", USAGE),
+  };
+  let (token, api_key) = credentials(&AppConfig::from_env())?;
+  print_table(destiny::vendors(token, api_key, vendor)?, format)
+}
+
+fn progress(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  if !args.is_empty() {
+    bail!("progress takes no other arguments\n\n{}", USAGE)
+  }
+  let (token, api_key) = credentials(&AppConfig::from_env())?;
+  print_table(destiny::progress(token, api_key)?, format)
+}
+
+fn pursuits(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  let mut incomplete = false;
+  for arg in &args {
+    match arg.as_str() {
+      "--incomplete" => incomplete = true,
+      other => bail!("Unexpected argument {:?}\n\n{}", other, USAGE),
+    }
+  }
+  let (token, api_key) = credentials(&AppConfig::from_env())?;
+  print_table(destiny::pursuits(token, api_key, incomplete)?, format)
+}
+
+fn collections(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  let mut filter = destiny::CollectionFilter::default();
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--source" => filter.source = Some(args.next().ok_or(format_err!("--source needs a value"))?.clone()),
+      "--season" => filter.season = Some(args.next().ok_or(format_err!("--season needs a value"))?.clone()),
+      "--missing" => filter.missing_only = true,
+      other => bail!("Unexpected argument {:?}\n\n{}", other, USAGE),
+    }
+  }
+  let (token, api_key) = credentials(&AppConfig::from_env())?;
+  print_table(destiny::collections(token, api_key, &filter)?, format)
+}
+
+fn search(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  if args.is_empty() {
+    bail!("search needs a name or hash\n\n{}", USAGE)
+  }
+  print_table(destiny::search_manifest(&args.join(" "))?, format)
+}
+
+fn history(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  if !args.is_empty() {
+    bail!("history takes no other arguments\n\n{}", USAGE)
+  }
+  print_table(destiny::snapshots(&membership_id()?)?, format)
+}
+
+fn diff(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  let since = match args.len() {
+    0 => None,
+    1 => Some(args[0]
+      .parse()
+      .with_context(|_| format!("snapshot {:?} isn't a number", args[0]))?),
+    _ => bail!("diff takes at most one snapshot\n\n{}", USAGE),
+  };
+  print_table(destiny::snapshot_diff(&membership_id()?, since)?, format)
+}
+
+// The Bungie.net account ACCESS_TOKEN belongs to, which snapshots and
+// loadouts are kept under.
+fn membership_id() -> Result<String> {
+  let (token, api_key) = credentials(&AppConfig::from_env())?;
+  Ok(destiny::account(token, api_key)?.membership_id)
+}
+
+fn logout(args: &[String]) -> Result<()> {
+  if !args.is_empty() {
+    bail!("logout takes no options\n\n{}", USAGE);
+  }
+  for path in destiny::wipe_local_data(false)? {
+    println!("Removed {}", path.display());
+  }
+  // Tokens only ever come from the environment, which we can't change for
+  // the shell that ran us. Bungie.net has no call to revoke them either, so
+  // they stay good until they expire.
+  if !AppConfig::from_env().access_token.is_empty() {
+    println!(
+      "ACCESS_TOKEN is still set - unset it and REFRESH_TOKEN to finish logging out. \
+       Bungie.net doesn't revoke them, so they work until they expire."
+    );
+  }
+  Ok(())
+}
+
+// Loadouts and history can't be fetched again, unlike the dumps, so they're
+// only ever deleted on purpose.
+fn purge(args: &[String]) -> Result<()> {
+  let yes = match args.len() {
+    0 => false,
+    1 if args[0] == "--yes" => true,
+    _ => bail!("purge takes only --yes\n\n{}", USAGE),
+  };
+  if !yes && !confirm("Delete the saved loadouts and inventory history for good?")? {
+    return Ok(println!("Nothing changed."));
+  }
+  for path in destiny::wipe_local_data(true)? {
+    println!("Removed {}", path.display());
+  }
+  Ok(())
+}
+
+fn loadout(args: &[String]) -> Result<()> {
+  let (format, args) = take_format(args)?;
+  let yes = args.iter().any(|a| a == "--yes");
+  let args = args.iter().filter(|a| *a != "--yes").collect::<Vec<_>>();
+
+  if args.is_empty() || args.len() > 2 {
+    bail!("loadout needs an action and a name\n\n{}", USAGE)
+  }
+  let action = args[0].as_str();
+  let name = args.get(1).map(|name| name.as_str());
+  if action == "list" {
+    return print_table(destiny::loadouts(&membership_id()?)?, format);
+  }
+  let name = name.ok_or(format_err!("loadout {} needs a name\n\n{}", action, USAGE))?;
+
+  match action {
+    "delete" => destiny::delete_loadout(&membership_id()?, name),
+    "save" => {
+      let (token, api_key) = credentials(&AppConfig::from_env())?;
+      let count = destiny::save_loadout(token, api_key, &membership_id()?, name)?;
+      Ok(println!("Saved {} equipped items as {:?}", count, name))
+    }
+    "plan" => {
+      let (token, api_key) = credentials(&AppConfig::from_env())?;
+      let plan = destiny::plan_loadout(token, api_key, &membership_id()?, name)?;
+      print_table(plan.table(), format)
+    }
+    "apply" => {
+      let (token, api_key) = credentials(&AppConfig::from_env())?;
+      let plan = destiny::plan_loadout(token.clone(), api_key.clone(), &membership_id()?, name)?;
+      if !yes {
+        print_table(plan.table(), format)?;
+        if !confirm("Make these moves?")? {
+          return Ok(println!("Nothing changed."));
+        }
+      }
+      print_table(destiny::apply_loadout(token, api_key, plan)?, format)
+    }
+    other => bail!("Unknown loadout action {:?}\n\n{}", other, USAGE),
+  }
+}
+
+fn confirm(question: &str) -> Result<bool> {
+  print!("{} [y/N] ", question);
+  io::stdout().flush()?;
+  let mut answer = String::new();
+  io::stdin().read_line(&mut answer)?;
+  Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{self, Value};
+
+use errors::*;
+
+use super::json_path;
+
+// (struct, field, issue) -> (times seen, an example path)
+type Findings = BTreeMap<(String, String, &'static str), (u64, String)>;
+
+lazy_static! {
+  // None until auditing is turned on.
+  static ref FINDINGS: Mutex<Option<Findings>> = Mutex::new(None);
+}
+
+/// One way the JSON we get differs from the structs we read it into.
+#[derive(Debug, Clone)]
+pub struct Drift {
+  type_name: String,
+  field: String,
+  issue: &'static str,
+  count: u64,
+  example: String,
+}
+
+impl Drift {
+  pub fn type_name(&self) -> String {
+    self.type_name.clone()
+  }
+
+  pub fn field(&self) -> String {
+    self.field.clone()
+  }
+
+  pub fn issue(&self) -> String {
+    self.issue.to_owned()
+  }
+
+  pub fn count(&self) -> String {
+    format!("{}", self.count)
+  }
+
+  pub fn example(&self) -> String {
+    self.example.clone()
+  }
+}
+
+/// Starts recording, for everything deserialized through `from_slice`, the
+/// JSON fields our structs don't model and the modeled fields that are
+/// absent or null.
+pub fn enable() {
+  let mut findings = FINDINGS.lock().unwrap();
+  if findings.is_none() {
+    *findings = Some(Findings::new());
+  }
+}
+
+/// Everything recorded so far, or None if auditing is off.
+pub fn report() -> Option<Vec<Drift>> {
+  let findings = FINDINGS.lock().unwrap();
+  findings.as_ref().map(|findings| {
+    findings
+      .iter()
+      .map(|(&(ref type_name, ref field, issue), &(count, ref example))| Drift {
+        type_name: type_name.clone(),
+        field: field.clone(),
+        issue,
+        count,
+        example: example.clone(),
+      })
+      .collect()
+  })
+}
+
+/// Like `json_path::from_slice`, but also audits `json` against `T` if
+/// auditing is on.
+pub fn from_slice<T: DeserializeOwned>(json: &[u8]) -> Result<T> {
+  if FINDINGS.lock().unwrap().is_none() {
+    return json_path::from_slice(json);
+  }
+
+  let value: Value = json_path::from_slice(json)?;
+  let mut found = Findings::new();
+  let result = T::deserialize(Audited {
+    value,
+    path: String::new(),
+    findings: &mut found,
+  });
+
+  if let Some(ref mut findings) = *FINDINGS.lock().unwrap() {
+    for (key, (count, example)) in found {
+      findings.entry(key).or_insert((0, example)).0 += count;
+    }
+  }
+  // Go around again for the error, since that knows where in the text it was.
+  result.or_else(|_| json_path::from_slice(json))
+}
+
+pub fn from_str<T: DeserializeOwned>(json: &str) -> Result<T> {
+  from_slice(json.as_bytes())
+}
+
+fn note(findings: &mut Findings, type_name: &str, field: &str, issue: &'static str, path: &str) {
+  findings
+    .entry((type_name.to_owned(), field.to_owned(), issue))
+    .or_insert((0, path.to_owned()))
+    .0 += 1;
+}
+
+fn join(path: &str, key: &str) -> String {
+  if path.is_empty() {
+    key.to_owned()
+  } else {
+    format!("{}.{}", path, key)
+  }
+}
+
+// Deserializes from an already-parsed value, noting at each struct how its
+// fields line up with the object's keys. Anything that isn't a struct,
+// sequence, map or option is left to serde_json's own handling of the value.
+struct Audited<'f> {
+  value: Value,
+  path: String,
+  findings: &'f mut Findings,
+}
+
+macro_rules! forward_to_value {
+  ($($method:ident)*) => {
+    $(
+      fn $method<V: Visitor<'de>>(self, visitor: V) -> ::std::result::Result<V::Value, serde_json::Error> {
+        self.value.$method(visitor)
+      }
+    )*
+  }
+}
+
+impl<'de, 'f> Deserializer<'de> for Audited<'f> {
+  type Error = serde_json::Error;
+
+  forward_to_value!(
+    deserialize_any deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+    deserialize_i64 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+    deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+    deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_identifier
+    deserialize_ignored_any
+  );
+
+  fn deserialize_unit_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    visitor: V,
+  ) -> ::std::result::Result<V::Value, serde_json::Error> {
+    self.value.deserialize_unit_struct(name, visitor)
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> ::std::result::Result<V::Value, serde_json::Error> {
+    visitor.visit_newtype_struct(self)
+  }
+
+  fn deserialize_tuple<V: Visitor<'de>>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> ::std::result::Result<V::Value, serde_json::Error> {
+    self.deserialize_seq(visitor)
+  }
+
+  fn deserialize_tuple_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> ::std::result::Result<V::Value, serde_json::Error> {
+    self.deserialize_seq(visitor)
+  }
+
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    variants: &'static [&'static str],
+    visitor: V,
+  ) -> ::std::result::Result<V::Value, serde_json::Error> {
+    self.value.deserialize_enum(name, variants, visitor)
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> ::std::result::Result<V::Value, serde_json::Error> {
+    match self.value {
+      Value::Null => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    }
+  }
+
+  fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> ::std::result::Result<V::Value, serde_json::Error> {
+    match self.value {
+      Value::Array(items) => visitor.visit_seq(Elements {
+        items: items.into_iter().enumerate(),
+        path: self.path,
+        findings: self.findings,
+      }),
+      other => other.deserialize_seq(visitor),
+    }
+  }
+
+  fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> ::std::result::Result<V::Value, serde_json::Error> {
+    match self.value {
+      Value::Object(map) => visitor.visit_map(Entries {
+        entries: map.into_iter(),
+        pending: None,
+        path: self.path,
+        findings: self.findings,
+      }),
+      other => other.deserialize_map(visitor),
+    }
+  }
+
+  fn deserialize_struct<V: Visitor<'de>>(
+    self,
+    name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> ::std::result::Result<V::Value, serde_json::Error> {
+    match self.value {
+      Value::Object(map) => {
+        for key in map.keys().filter(|key| !fields.contains(&key.as_str())) {
+          note(self.findings, name, key, "not modeled", &join(&self.path, key));
+        }
+        for field in fields.iter().filter(|field| map.get(**field).map_or(true, |v| v.is_null())) {
+          note(self.findings, name, field, "missing", &join(&self.path, field));
+        }
+        visitor.visit_map(Entries {
+          entries: map.into_iter(),
+          pending: None,
+          path: self.path,
+          findings: self.findings,
+        })
+      }
+      other => other.deserialize_struct(name, fields, visitor),
+    }
+  }
+}
+
+struct Elements<'f> {
+  items: ::std::iter::Enumerate<::std::vec::IntoIter<Value>>,
+  path: String,
+  findings: &'f mut Findings,
+}
+
+impl<'de, 'f> SeqAccess<'de> for Elements<'f> {
+  type Error = serde_json::Error;
+
+  fn next_element_seed<T: DeserializeSeed<'de>>(
+    &mut self,
+    seed: T,
+  ) -> ::std::result::Result<Option<T::Value>, serde_json::Error> {
+    match self.items.next() {
+      Some((index, value)) => seed
+        .deserialize(Audited {
+          value,
+          path: format!("{}[{}]", self.path, index),
+          findings: &mut *self.findings,
+        })
+        .map(Some),
+      None => Ok(None),
+    }
+  }
+}
+
+struct Entries<'f> {
+  entries: serde_json::map::IntoIter,
+  pending: Option<(String, Value)>,
+  path: String,
+  findings: &'f mut Findings,
+}
+
+impl<'de, 'f> MapAccess<'de> for Entries<'f> {
+  type Error = serde_json::Error;
+
+  fn next_key_seed<K: DeserializeSeed<'de>>(
+    &mut self,
+    seed: K,
+  ) -> ::std::result::Result<Option<K::Value>, serde_json::Error> {
+    match self.entries.next() {
+      Some((key, value)) => {
+        let deserialized = seed.deserialize(Key(key.clone()))?;
+        self.pending = Some((key, value));
+        Ok(Some(deserialized))
+      }
+      None => Ok(None),
+    }
+  }
+
+  fn next_value_seed<V: DeserializeSeed<'de>>(
+    &mut self,
+    seed: V,
+  ) -> ::std::result::Result<V::Value, serde_json::Error> {
+    let (key, value) = self
+      .pending
+      .take()
+      .ok_or_else(|| de::Error::custom("value asked for before its key"))?;
+    seed.deserialize(Audited {
+      value,
+      path: join(&self.path, entry_key(&key)),
+      findings: &mut *self.findings,
+    })
+  }
+}
+
+// Maps keyed by numbers are keyed by hashes and ids - of characters, items,
+// memberships - which don't belong in a report anyone can read, and would
+// only make one example path per account anyway.
+fn entry_key(key: &str) -> &str {
+  if !key.is_empty() && key.chars().all(|c| c.is_ascii_digit()) {
+    "*"
+  } else {
+    key
+  }
+}
+
+// Object keys are always strings in JSON, but maps keyed by numbers (hashes,
+// mostly) expect them parsed, as serde_json does.
+struct Key(String);
+
+macro_rules! parse_key {
+  ($($method:ident => $visit:ident: $t:ty,)*) => {
+    $(
+      fn $method<V: Visitor<'de>>(self, visitor: V) -> ::std::result::Result<V::Value, serde_json::Error> {
+        match self.0.parse::<$t>() {
+          Ok(n) => visitor.$visit(n),
+          Err(_) => Value::String(self.0).$method(visitor),
+        }
+      }
+    )*
+  }
+}
+
+impl<'de> Deserializer<'de> for Key {
+  type Error = serde_json::Error;
+
+  parse_key!(
+    deserialize_i8 => visit_i8: i8,
+    deserialize_i16 => visit_i16: i16,
+    deserialize_i32 => visit_i32: i32,
+    deserialize_i64 => visit_i64: i64,
+    deserialize_u8 => visit_u8: u8,
+    deserialize_u16 => visit_u16: u16,
+    deserialize_u32 => visit_u32: u32,
+    deserialize_u64 => visit_u64: u64,
+  );
+
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> ::std::result::Result<V::Value, serde_json::Error> {
+    visitor.visit_string(self.0)
+  }
+
+  forward_to_deserialize_any! {
+    bool f32 f64 char str string bytes byte_buf option unit unit_struct newtype_struct seq
+    tuple tuple_struct map struct enum identifier ignored_any
+  }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::dtos::{CharacterComponent, ItemResponse};
+
+/// A fetched profile: every item, and the characters they were found on.
+#[derive(Clone)]
+pub struct Profile {
+  pub items: Vec<ItemResponse>,
+  pub characters: Vec<CharacterComponent>,
+}
+
+struct Entry {
+  fetched: Instant,
+  profile: Profile,
+}
+
+// Profiles are kept by Bungie.net account, so every session of an account
+// sees the same one, and a change made from any of them drops it for all.
+// Requests only come with a token, so each token points at its account.
+struct Cache {
+  ttl: Option<Duration>,
+  entries: HashMap<String, Entry>,
+  accounts: HashMap<String, String>,
+}
+
+lazy_static! {
+  static ref CACHE: Mutex<Cache> = Mutex::new(Cache {
+    ttl: None,
+    entries: HashMap::new(),
+    accounts: HashMap::new(),
+  });
+}
+
+/// Turns on caching of profiles for `ttl`. Until this is called nothing is
+/// cached, which suits one-shot commands.
+pub fn enable(ttl: Duration) {
+  let mut cache = CACHE.lock().unwrap();
+  cache.ttl = Some(ttl);
+}
+
+pub fn get(token: &str) -> Option<Profile> {
+  let mut cache = CACHE.lock().unwrap();
+  let ttl = cache.ttl?;
+  cache.entries.retain(|_, entry| entry.fetched.elapsed() < ttl);
+  let Cache {
+    ref mut entries,
+    ref mut accounts,
+    ..
+  } = *cache;
+  accounts.retain(|_, account| entries.contains_key(account));
+  let account = accounts.get(token)?;
+  entries.get(account).map(|entry| entry.profile.clone())
+}
+
+/// Keeps `profile` as the account `membership_id`'s, fetched with `token`.
+pub fn put(token: &str, membership_id: &str, profile: Profile) {
+  let mut cache = CACHE.lock().unwrap();
+  if cache.ttl.is_some() {
+    cache.entries.insert(
+      membership_id.to_owned(),
+      Entry {
+        fetched: Instant::now(),
+        profile,
+      },
+    );
+    cache.accounts.insert(token.to_owned(), membership_id.to_owned());
+  }
+}
+
+/// Drops the profile cached for `token`'s account, after it's been changed
+/// in game or the token is going away.
+pub fn forget(token: &str) {
+  let mut cache = CACHE.lock().unwrap();
+  if let Some(account) = cache.accounts.remove(token) {
+    cache.entries.remove(&account);
+  }
+}
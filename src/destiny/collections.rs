@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+use rusqlite::Connection;
+
+use super::dtos::{self, CharacterComponent, CollectibleDefinition, DestinyProfileResponse,
+                  InventoryItemDefinition, KiosksComponent, SeasonDefinition, VendorDefinition};
+use super::dtos::enums::TierType;
+
+/// Which collectibles to list.
+#[derive(Debug, Clone, Default)]
+pub struct CollectionFilter {
+  /// Part of the kiosk's name or of where the item drops, e.g. "Xur".
+  pub source: Option<String>,
+  /// A season number, or part of the season's name.
+  pub season: Option<String>,
+  pub missing_only: bool,
+}
+
+impl CollectionFilter {
+  fn accepts(&self, collectible: &Collectible) -> bool {
+    let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.to_lowercase());
+    if self.missing_only && collectible.acquired {
+      return false;
+    }
+    if let Some(ref source) = self.source {
+      if !contains(&collectible.kiosk, source) && !contains(&collectible.source(), source) {
+        return false;
+      }
+    }
+    if let Some(ref season) = self.season {
+      let number = collectible.season.as_ref().and_then(|s| s.season_number);
+      if number.map(|n| n.to_string()) != Some(season.clone()) && !contains(&collectible.season(), season) {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+/// An exotic or legendary item a kiosk can hand out, and whether its owner
+/// has unlocked it.
+#[derive(Debug, Clone)]
+pub struct Collectible {
+  owner: String,
+  kiosk: String,
+  item: InventoryItemDefinition,
+  collectible: Option<CollectibleDefinition>,
+  season: Option<SeasonDefinition>,
+  acquired: bool,
+}
+
+impl Collectible {
+  pub fn owner(&self) -> String {
+    self.owner.clone()
+  }
+
+  pub fn kiosk(&self) -> String {
+    self.kiosk.clone()
+  }
+
+  pub fn item_name(&self) -> String {
+    self.item.display_properties.name.clone().unwrap_or_default()
+  }
+
+  pub fn tier(&self) -> String {
+    format!("{:?}", self.item.inventory.tier_type)
+  }
+
+  pub fn item_kind(&self) -> String {
+    self.item.item_type_display_name.clone()
+  }
+
+  pub fn source(&self) -> String {
+    self.collectible
+      .clone()
+      .and_then(|def| def.source_string)
+      .unwrap_or_default()
+  }
+
+  pub fn season(&self) -> String {
+    match self.season {
+      Some(ref def) => def.display_properties
+        .name
+        .clone()
+        .filter(|name| !name.is_empty())
+        .or(def.season_number.map(|n| format!("Season {}", n)))
+        .unwrap_or_default(),
+      None => "".to_owned(),
+    }
+  }
+
+  pub fn is_acquired(&self) -> bool {
+    self.acquired
+  }
+
+  pub fn status(&self) -> String {
+    let status = if self.acquired { "acquired" } else { "missing" };
+    status.to_owned()
+  }
+}
+
+/// The exotic and legendary kiosk items on the account, and each
+/// character's, that `filter` accepts.
+pub fn collectibles(
+  profile: &DestinyProfileResponse,
+  filter: &CollectionFilter,
+  db: &Connection,
+) -> Vec<Collectible> {
+  let mut found = Vec::new();
+  let mut kiosks = HashMap::new();
+
+  if let Some(ref account) = profile.profile_kiosks {
+    found.extend(owned("Account".to_owned(), &account.data, &mut kiosks, db));
+  }
+  if let Some(ref per_character) = profile.character_kiosks {
+    let characters: HashMap<String, CharacterComponent> = profile
+      .characters
+      .as_ref()
+      .map(|chars| chars.data.clone())
+      .unwrap_or_default();
+    for (id, component) in &per_character.data {
+      let owner = characters.get(id).map_or(id.clone(), |c| c.label());
+      found.extend(owned(owner, component, &mut kiosks, db));
+    }
+  }
+
+  found.retain(|collectible| filter.accepts(collectible));
+  found
+}
+
+// Everything each kiosk in `component` could hold. A kiosk lists every item
+// its owner has unlocked - `can_acquire` only says whether one can be pulled
+// right now - so what it lists is acquired, and the rest of its vendor's
+// item list is missing.
+fn owned(
+  owner: String,
+  component: &KiosksComponent,
+  kiosks: &mut HashMap<u32, Option<VendorDefinition>>,
+  db: &Connection,
+) -> Vec<Collectible> {
+  let mut found = Vec::new();
+  for (hash, items) in &component.kiosk_items {
+    let hash: u32 = match hash.parse() {
+      Ok(hash) => hash,
+      Err(_) => continue,
+    };
+    let kiosk = match *kiosks
+      .entry(hash)
+      .or_insert_with(|| dtos::find_definition("DestinyVendorDefinition", hash, db))
+    {
+      Some(ref kiosk) => kiosk.clone(),
+      None => continue,
+    };
+    let acquired: HashSet<usize> = items.iter().map(|item| item.index).collect();
+
+    for (index, listed) in kiosk.item_list.iter().enumerate() {
+      let item: InventoryItemDefinition =
+        match dtos::find_definition("DestinyInventoryItemDefinition", listed.item_hash, db) {
+          Some(item) => item,
+          None => continue,
+        };
+      match item.inventory.tier_type {
+        TierType::Exotic | TierType::Legendary => (),
+        _ => continue,
+      }
+      found.push(Collectible {
+        owner: owner.clone(),
+        kiosk: kiosk.display_properties.name.clone().unwrap_or_default(),
+        collectible: item
+          .collectible_hash
+          .and_then(|hash| dtos::find_definition("DestinyCollectibleDefinition", hash, db)),
+        season: item
+          .season_hash
+          .and_then(|hash| dtos::find_definition("DestinySeasonDefinition", hash, db)),
+        acquired: acquired.contains(&index),
+        item,
+      });
+    }
+  }
+  found
+}
@@ -87,6 +87,13 @@ enum_number!(TierType {
   Exotic = 6,
 });
 
+enum_number!(ClassType {
+  Titan = 0,
+  Hunter = 1,
+  Warlock = 2,
+  Unknown = 3,
+});
+
 enum_number!(BungieMemberType {
   TigerXbox = 1,
   TigerPsn = 2,
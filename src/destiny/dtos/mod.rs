@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::cmp;
 use errors::*;
 
@@ -10,8 +10,10 @@ pub trait Deser
   fn deser(value: Download) -> Result<Self>;
 }
 
-use destiny::{Download, write_body};
+use destiny::{audit, Download};
+use destiny::wishlist::RollMatch;
 use failure::ResultExt;
+use metrics;
 
 macro_rules! body_wrapper{
   ($inner:ident, $outer:ident) => {
@@ -26,10 +28,12 @@ macro_rules! body_wrapper{
 
   impl Deser for $outer {
     fn deser(value: Download) -> Result<$outer> {
-      let (outurl, json_out, body_chunk) = value;
+      let (outurl, dumped, body_chunk) = value;
       info!("Derializing: {}", outurl);
-      write_body(&json_out, &body_chunk);
-      Ok(serde_json::from_slice(&body_chunk).with_context(|_| format!("deserializing JSON: Source URL: {} recorded at {:?}", outurl, json_out))?)
+      Ok(audit::from_slice(&body_chunk).with_context(|e| match dumped {
+        Some(ref path) => format!("deserializing JSON: {}: Source URL: {} recorded at {:?}", e, outurl, path),
+        None => format!("deserializing JSON: {}: Source URL: {} (set DEBUG_DUMP_DIR to record responses)", e, outurl),
+      })?)
     }
   }
   }
@@ -39,6 +43,28 @@ body_wrapper!(ItemResponse, ItemResponseBody);
 body_wrapper!(UserMembershipData, UserResponseBody);
 body_wrapper!(DestinyManifest, ManifestResponseBody);
 body_wrapper!(DestinyProfileResponse, ProfileResponseBody);
+body_wrapper!(i32, ActionResponseBody);
+body_wrapper!(DestinyVendorsResponse, VendorsResponseBody);
+body_wrapper!(DestinyVendorResponse, VendorResponseBody);
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemTransferRequest {
+  pub item_reference_hash: u32,
+  pub stack_size: i32,
+  pub transfer_to_vault: bool,
+  pub item_id: String,
+  pub character_id: String,
+  pub membership_type: enums::BungieMemberType,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemSetActionRequest {
+  pub item_ids: Vec<String>,
+  pub character_id: String,
+  pub membership_type: enums::BungieMemberType,
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -57,51 +83,113 @@ pub struct DestinyManifest {
 #[serde(rename_all = "camelCase")]
 pub struct DestinyProfileResponse {
   pub profile_inventory: Option<InventoryComponentResponse>,
+  pub characters: Option<CharactersComponentResponse>,
   pub character_equipment: Option<CharacterEquipmentComponentResponse>,
   pub character_inventories: Option<CharacterEquipmentComponentResponse>,
+  pub character_progressions: Option<CharacterProgressionComponentResponse>,
+  pub profile_kiosks: Option<KiosksComponentResponse>,
+  pub character_kiosks: Option<CharacterKiosksComponentResponse>,
+  #[serde(default)]
   pub item_components: ItemComponentSet,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct ItemComponentSet {
+  pub objectives: Option<ItemObjectivesComponentResponse>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemObjectivesComponentResponse {
+  #[serde(default)]
+  pub data: HashMap<String, ItemObjectivesComponent>,
+  pub privacy: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemObjectivesComponent {
+  pub objectives: Vec<ObjectiveProgress>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ItemResponse {
-  // stats SingleItemStats
-  // sockets SingleItemSockets
   pub character_id: Option<String>, // API says i64...
   pub item: Option<SingleItem>,
   pub instance: Option<SingleItemInstance>,
+  pub stats: Option<SingleItemStats>,
   pub sockets: Option<ItemSocketsComponent>,
   bucket: Option<InventoryBucketDefinition>,
   item_def: Option<InventoryItemDefinition>,
 
   #[serde(skip)]
   pub plug_defs: Vec<ItemSocketState>,
+  #[serde(skip)]
+  pub stat_defs: HashMap<u32, StatDefinition>,
+  #[serde(skip)]
+  pub roll: Option<RollMatch>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemSummary {
+  pub name: String,
+  pub bucket: String,
+  pub tier: String,
+  pub kind: String,
+  pub equipped: bool,
+  pub power: i32,
+  pub infusion_power: String,
+  pub infusion_category: u32,
+  pub stats: BTreeMap<String, i32>,
+  pub roll: String,
 }
 
 use rusqlite::Connection;
-use serde_json;
+use serde::de::DeserializeOwned;
 
-fn fetch_plug_def(hash: i32, db: &Connection) -> Result<InventoryItemDefinition> {
-  let mut stmt =
-    db.prepare_cached("select json from DestinyInventoryItemDefinition where id = ?1")?;
-  stmt.query_row(&[&hash], |row| {
+/// Looks up a definition by hash in one of the manifest's tables, e.g.
+/// DestinyVendorDefinition.
+pub fn fetch_definition<T: DeserializeOwned>(table: &str, hash: u32, db: &Connection) -> Result<T> {
+  let mut stmt = db.prepare_cached(&format!("select json from {} where id = ?1", table))?;
+  let def = stmt.query_row(&[&(hash as i32)], |row| {
       let json: String = row.get(0);
-      serde_json::from_str(&json).with_context(|_| format!("deserializing JSON: {}", hash))
+      audit::from_str(&json).with_context(|e| format!("deserializing {} {}: {}", table, hash, e))
     })
     .map_err(|e| Error::from(e))
-    .and_then(|res| Ok(res?))
+    .and_then(|res| Ok(res?));
+  metrics::definition_lookup(def.is_ok());
+  def
+}
+
+/// Like `fetch_definition`, for when a missing definition - usually one
+/// newer than our manifest - should just leave something out.
+pub fn find_definition<T: DeserializeOwned>(table: &str, hash: u32, db: &Connection) -> Option<T> {
+  match fetch_definition(table, hash, db) {
+    Ok(def) => Some(def),
+    Err(e) => {
+      debug!("No {} for {}: {}", table, hash, e);
+      None
+    }
+  }
+}
+
+fn fetch_plug_def(hash: u32, db: &Connection) -> Result<InventoryItemDefinition> {
+  fetch_definition("DestinyInventoryItemDefinition", hash, db)
+}
+
+fn fetch_stat_def(hash: u32, db: &Connection) -> Result<StatDefinition> {
+  fetch_definition("DestinyStatDefinition", hash, db)
 }
 
 impl ItemResponse {
   pub fn fetch_component_defs<'f, 'g>(&'f mut self, db: &'g Connection) {
     match self.fetch_item_def(db)
       .and(self.fetch_bucket_def(db))
-      .and(self.fetch_plug_defs(db)) {
+      .and(self.fetch_plug_defs(db))
+      .and(self.fetch_stat_defs(db)) {
       Ok(_) => (),
       Err(e) => println!("Problem getting defs for:\n {:?}: \n{:?}", self, e),
     }
@@ -115,11 +203,15 @@ impl ItemResponse {
       Some(row) => {
         let json: String = row?.get(0);
         let item: InventoryItemDefinition =
-          serde_json::from_str(&json).with_context(|_| format!("deserializing JSON: {}", json))?;
+          audit::from_str(&json).with_context(|e| format!("deserializing definition: {}", e))?;
         self.item_def = Some(item);
+        metrics::definition_lookup(true);
         Ok(())
       }
-      None => bail!("No item def for hash!"),
+      None => {
+        metrics::definition_lookup(false);
+        bail!("No item def for hash!")
+      }
     }
   }
 
@@ -131,11 +223,15 @@ impl ItemResponse {
       Some(row) => {
         let json: String = row?.get(0);
         let bucket: InventoryBucketDefinition =
-          serde_json::from_str(&json).with_context(|_| format!("deserializing JSON: {}", json))?;
+          audit::from_str(&json).with_context(|e| format!("deserializing definition: {}", e))?;
         self.bucket = Some(bucket);
+        metrics::definition_lookup(true);
         Ok(())
       }
-      None => bail!("No bucket def for hash!"),
+      None => {
+        metrics::definition_lookup(false);
+        bail!("No bucket def for hash!")
+      }
     }
   }
 
@@ -146,23 +242,160 @@ impl ItemResponse {
         let mut sock = sock.clone();
         match sock.plug_hash {
           Some(hash) => {
-            match fetch_plug_def(hash as i32, db) {
+            match fetch_plug_def(hash, db) {
               Ok(v) => {
                 sock.plug_def = Some(v);
               }
               Err(e) => {
-                println!("{:?}", e);
+                warn!("No plug definition for {}: {}", hash, e);
               }
             }
           }
           None => (),
         };
+        sock.reusable_plug_defs = sock.reusable_plug_hashes
+          .clone()
+          .unwrap_or_default()
+          .iter()
+          .filter_map(|hash| match fetch_plug_def(*hash, db) {
+            Ok(v) => Some(v),
+            Err(e) => {
+              warn!("No plug definition for {}: {}", hash, e);
+              None
+            }
+          })
+          .collect();
         sock
       })
       .collect();
     Ok(())
   }
 
+  /// One row per socket holding a plug, for the perks view.
+  pub fn perk_rows(&self) -> Vec<PerkRow> {
+    self.plug_defs
+      .iter()
+      .enumerate()
+      .filter(|&(_, sock)| sock.plug_hash.is_some())
+      .map(|(index, sock)| PerkRow {
+        item_name: self.item_name(),
+        socket_index: index,
+        socket: sock.clone(),
+      })
+      .collect()
+  }
+
+  fn fetch_stat_defs<'f, 'g>(&'f mut self, db: &'g Connection) -> Result<()> {
+    for stat in self.item_stats() {
+      match fetch_stat_def(stat.stat_hash, db) {
+        Ok(def) => {
+          self.stat_defs.insert(stat.stat_hash, def);
+        }
+        Err(e) => {
+          warn!("No stat definition for {}: {}", stat.stat_hash, e);
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn item_stats(&self) -> Vec<Stat> {
+    self.stats
+      .clone()
+      .and_then(|statc| statc.data.map(|stats| stats.stats.values().cloned().collect()))
+      .unwrap_or_default()
+  }
+
+  /// Stat values keyed by their display name, e.g. "Mobility" or "Impact".
+  pub fn named_stats(&self) -> BTreeMap<String, i32> {
+    self.item_stats()
+      .iter()
+      .filter_map(|stat| {
+        self.stat_defs
+          .get(&stat.stat_hash)
+          .and_then(|def| def.display_properties.name.clone())
+          .map(|name| (name, stat.value))
+      })
+      .collect()
+  }
+
+  fn stat_named(&self, name: &str) -> String {
+    self.named_stats().get(name).map_or("".to_owned(), |value| format!("{}", value))
+  }
+
+  pub fn mobility(&self) -> String {
+    self.stat_named("Mobility")
+  }
+
+  pub fn resilience(&self) -> String {
+    self.stat_named("Resilience")
+  }
+
+  pub fn recovery(&self) -> String {
+    self.stat_named("Recovery")
+  }
+
+  pub fn impact(&self) -> String {
+    self.stat_named("Impact")
+  }
+
+  pub fn range(&self) -> String {
+    self.stat_named("Range")
+  }
+
+  pub fn stability(&self) -> String {
+    self.stat_named("Stability")
+  }
+
+  pub fn handling(&self) -> String {
+    self.stat_named("Handling")
+  }
+
+  pub fn reload_speed(&self) -> String {
+    self.stat_named("Reload Speed")
+  }
+
+  pub fn rounds_per_minute(&self) -> String {
+    self.stat_named("Rounds Per Minute")
+  }
+
+  pub fn magazine(&self) -> String {
+    self.stat_named("Magazine")
+  }
+
+  pub fn summary(&self) -> ItemSummary {
+    ItemSummary {
+      name: self.item_name(),
+      bucket: self.bucket_name(),
+      tier: self.tier(),
+      kind: self.item_kind(),
+      equipped: self.instance.clone().map_or(false, |i| i.data.is_equipped),
+      power: self.stat_num(),
+      infusion_power: self.infusion_power(),
+      infusion_category: self.infusion_category_hash(),
+      stats: self.named_stats(),
+      roll: self.roll(),
+    }
+  }
+
+  /// Hashes and names of every plug currently in, or selectable for, this
+  /// item's sockets.
+  pub fn available_plugs(&self) -> Vec<(u32, String)> {
+    self.plug_defs
+      .iter()
+      .flat_map(|sock| sock.plug_def.iter().chain(sock.reusable_plug_defs.iter()))
+      .map(|plug| (plug.hash, plug.display_properties.name.clone().unwrap_or_default()))
+      .collect()
+  }
+
+  pub fn roll(&self) -> String {
+    self.roll.clone().map_or("".to_owned(), |r| format!("{}/{}", r.matched, r.wanted))
+  }
+
+  pub fn roll_complete(&self) -> bool {
+    self.roll.clone().map_or(false, |r| r.is_complete())
+  }
+
   fn plug_hashes(&self) -> Vec<ItemSocketState> {
     self.sockets
       .clone()
@@ -186,6 +419,51 @@ impl ItemResponse {
     status
   }
 
+  /// The equipment slot this item goes in, even while it's in the vault.
+  pub fn slot_hash(&self) -> u32 {
+    self.item_def.clone().map_or(0, |def| def.inventory.bucket_type_hash)
+  }
+
+  /// The class that can use this item; `ClassType::Unknown` means any.
+  pub fn class_type(&self) -> enums::ClassType {
+    self.item_def
+      .clone()
+      .and_then(|def| def.class_type)
+      .unwrap_or(enums::ClassType::Unknown)
+  }
+
+  pub fn is_exotic(&self) -> bool {
+    self.item_def.clone().map_or(false, |def| def.inventory.tier_type == enums::TierType::Exotic)
+  }
+
+  pub fn instance_id(&self) -> Option<String> {
+    self.item.clone().and_then(|i| i.data.item_instance_id)
+  }
+
+  /// The label of the character holding this item, or Vault.
+  pub fn location(&self, characters: &[CharacterComponent]) -> String {
+    match self.character_id {
+      Some(ref id) => characters
+        .iter()
+        .find(|c| &c.character_id == id)
+        .map_or(id.clone(), |c| c.label()),
+      None => "Vault".to_owned(),
+    }
+  }
+
+  pub fn is_equipped(&self) -> bool {
+    self.instance.clone().map_or(false, |i| i.data.is_equipped)
+  }
+
+  pub fn is_locked(&self) -> bool {
+    self.item.clone().map_or(false, |i| i.data.state == enums::ItemState::Locked)
+  }
+
+  /// The owning character's id, or "Vault" for items not on a character.
+  pub fn character(&self) -> String {
+    self.character_id.clone().unwrap_or("Vault".to_owned())
+  }
+
   pub fn bucket_name(&self) -> String {
     self.bucket.clone().map_or("".to_owned(),
                                |b| b.display_properties.name.unwrap_or_default())
@@ -222,6 +500,10 @@ impl ItemResponse {
     format!("{}", self.stat_num())
   }
 
+  pub fn power(&self) -> i32 {
+    self.stat_num()
+  }
+
   fn stat_num(&self) -> i32 {
     self.instance.clone().map_or(0,
                                  |inst| inst.data.primary_stat.map(|s| s.value).unwrap_or(0))
@@ -259,6 +541,19 @@ pub struct SingleItemInstance {
   pub privacy: i32,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleItemStats {
+  pub data: Option<ItemStats>,
+  pub privacy: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemStats {
+  pub stats: HashMap<String, Stat>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ItemSocketsComponent {
@@ -280,20 +575,33 @@ pub struct ItemSocketState {
   pub enable_fail_indexes: Option<Vec<i32>>,
   pub reusable_plug_hashes: Option<Vec<u32>>,
   plug_def: Option<InventoryItemDefinition>,
+  #[serde(skip)]
+  reusable_plug_defs: Vec<InventoryItemDefinition>,
 }
 
 impl ItemSocketState {
-  // pub fn plug_name(&self) -> String {
-  // self.plug_def.clone().map_or("".to_owned(),
-  // |plug| plug.display_properties.name.unwrap_or_default())
-  // }
-  // pub fn plug_type(&self) -> String {
-  // self.plug_def.clone().map_or("".to_owned(), |plug| plug.item_type_display_name)
-  // }
-  // pub fn plug_tier(&self) -> String {
-  // format!("{:?}", self.tier())
-  // }
-  //
+  pub fn plug_name(&self) -> String {
+    self.plug_def.clone().map_or("".to_owned(),
+                                 |plug| plug.display_properties.name.unwrap_or_default())
+  }
+
+  pub fn plug_type(&self) -> String {
+    self.plug_def.clone().map_or("".to_owned(), |plug| plug.item_type_display_name)
+  }
+
+  pub fn plug_tier(&self) -> String {
+    format!("{:?}", self.tier())
+  }
+
+  /// Names of the other plugs this socket could hold without rerolling.
+  pub fn alternatives(&self) -> String {
+    self.reusable_plug_defs
+      .iter()
+      .filter(|plug| Some(plug.hash) != self.plug_hash)
+      .map(|plug| plug.display_properties.name.clone().unwrap_or_default())
+      .collect::<Vec<_>>()
+      .join(", ")
+  }
 
   fn tier(&self) -> enums::TierType {
     self.plug_def.clone().map_or(enums::TierType::Unknown, |plug| plug.inventory.tier_type)
@@ -305,10 +613,10 @@ impl ItemSocketState {
     })
   }
 
-  // pub fn is_enabled(&self) -> String {
-  // format!("{}", self.is_enabled)
-  // }
-  //
+  pub fn enabled(&self) -> String {
+    let enabled = if self.is_enabled { "yes" } else { "no" };
+    enabled.to_owned()
+  }
 
   fn bumps_power(&self) -> bool {
     let cat = self.category_id();
@@ -317,6 +625,47 @@ impl ItemSocketState {
   }
 }
 
+#[derive(Debug, Clone)]
+pub struct PerkRow {
+  item_name: String,
+  socket_index: usize,
+  socket: ItemSocketState,
+}
+
+impl PerkRow {
+  pub fn item_name(&self) -> String {
+    self.item_name.clone()
+  }
+
+  pub fn socket_index(&self) -> String {
+    format!("{}", self.socket_index)
+  }
+
+  pub fn plug_name(&self) -> String {
+    self.socket.plug_name()
+  }
+
+  pub fn plug_type(&self) -> String {
+    self.socket.plug_type()
+  }
+
+  pub fn plug_tier(&self) -> String {
+    self.socket.plug_tier()
+  }
+
+  pub fn category_id(&self) -> String {
+    self.socket.category_id()
+  }
+
+  pub fn enabled(&self) -> String {
+    self.socket.enabled()
+  }
+
+  pub fn alternatives(&self) -> String {
+    self.socket.alternatives()
+  }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Item {
@@ -330,6 +679,8 @@ pub struct Item {
   pub quantity: i32,
   pub bucket_hash: u32,
   pub state: enums::ItemState,
+  // Set on bounties and other pursuits that time out.
+  pub expiration_date: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -356,6 +707,7 @@ pub struct InventoryBucketDefinition {
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct InventoryItemDefinition {
+  pub hash: u32,
   pub display_properties: DisplayProperties,
 
   pub item_type_display_name: String,
@@ -365,6 +717,9 @@ pub struct InventoryItemDefinition {
   pub plug: Option<PlugDefinition>,
   pub investment_stats: Vec<InvestmentStatDefinition>,
   pub inventory: InventoryBlockDefinition,
+  pub class_type: Option<enums::ClassType>,
+  pub collectible_hash: Option<u32>,
+  pub season_hash: Option<u32>,
 }
 // many fields omitted. See online docs
 
@@ -397,6 +752,22 @@ pub struct PlugDefinition {
   pub enabled_rules: Vec<PlugRuleDefinition>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StatDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  pub stat_category: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SandboxPerkDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  pub is_displayable: bool,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlugRuleDefinition {
@@ -436,6 +807,31 @@ pub struct CharacterEquipmentComponentResponse {
   pub privacy: i32,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CharactersComponentResponse {
+  pub data: HashMap<String, CharacterComponent>,
+  pub privacy: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterComponent {
+  // many fields omitted, e.g. race, gender, emblem
+  pub character_id: String,
+  pub class_type: enums::ClassType,
+  pub light: i32,
+}
+
+impl CharacterComponent {
+  /// The class name, with enough of the id to tell same-class characters
+  /// apart.
+  pub fn label(&self) -> String {
+    let id = &self.character_id;
+    format!("{:?} …{}", self.class_type, &id[id.len().saturating_sub(4)..])
+  }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct InventoryComponentResponse {
@@ -490,3 +886,375 @@ impl UserInfoCard {
     Ok(self.membership_id.parse()?)
   }
 }
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinyVendorsResponse {
+  pub vendors: Option<VendorsComponentResponse>,
+  pub categories: Option<VendorCategoriesComponentResponse>,
+  pub sales: Option<VendorSalesComponentResponse>,
+  #[serde(default)]
+  pub item_components: HashMap<String, VendorItemComponentSet>,
+}
+
+/// What GetVendor returns for a single vendor.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinyVendorResponse {
+  pub vendor: Option<SingleVendor>,
+  pub categories: Option<SingleVendorCategories>,
+  pub sales: Option<VendorSaleItemSetComponent>,
+  pub item_components: Option<VendorItemComponentSet>,
+}
+
+impl DestinyVendorResponse {
+  /// The same data, shaped as though it came from GetVendors.
+  pub fn into_vendors(self) -> DestinyVendorsResponse {
+    let hash = self.vendor.as_ref().map_or(0, |v| v.data.vendor_hash).to_string();
+    DestinyVendorsResponse {
+      vendors: self.vendor.map(|v| VendorsComponentResponse { data: keyed(&hash, v.data) }),
+      categories: self.categories.map(|c| VendorCategoriesComponentResponse { data: keyed(&hash, c.data) }),
+      sales: self.sales.map(|s| VendorSalesComponentResponse { data: keyed(&hash, s) }),
+      item_components: self.item_components.map(|ic| keyed(&hash, ic)).unwrap_or_default(),
+    }
+  }
+}
+
+fn keyed<T>(key: &str, value: T) -> HashMap<String, T> {
+  let mut map = HashMap::new();
+  map.insert(key.to_owned(), value);
+  map
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleVendor {
+  pub data: VendorComponent,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleVendorCategories {
+  pub data: VendorCategoriesComponent,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorsComponentResponse {
+  pub data: HashMap<String, VendorComponent>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorComponent {
+  pub vendor_hash: u32,
+  pub enabled: bool,
+  pub can_purchase: bool,
+  pub next_refresh_date: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorCategoriesComponentResponse {
+  pub data: HashMap<String, VendorCategoriesComponent>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorCategoriesComponent {
+  pub categories: Vec<VendorCategory>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorCategory {
+  pub display_category_index: usize,
+  pub item_indexes: Vec<i32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorSalesComponentResponse {
+  pub data: HashMap<String, VendorSaleItemSetComponent>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorSaleItemSetComponent {
+  pub sale_items: HashMap<String, VendorSaleItemComponent>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorSaleItemComponent {
+  pub vendor_item_index: i32,
+  pub item_hash: u32,
+  pub quantity: i32,
+  pub costs: Vec<ItemQuantity>,
+  // A bitmask; 0 means it can be bought.
+  pub sale_status: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemQuantity {
+  pub item_hash: u32,
+  pub quantity: i32,
+}
+
+// Item components for what a vendor sells, keyed by vendor item index.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorItemComponentSet {
+  pub sockets: Option<VendorItemSocketsComponentResponse>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorItemSocketsComponentResponse {
+  #[serde(default)]
+  pub data: HashMap<String, ItemSockets>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  #[serde(default)]
+  pub display_categories: Vec<DisplayCategoryDefinition>,
+  // What a kiosk's items are indexes into.
+  #[serde(default)]
+  pub item_list: Vec<VendorItemDefinition>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorItemDefinition {
+  pub item_hash: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplayCategoryDefinition {
+  pub display_properties: DisplayProperties,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterProgressionComponentResponse {
+  pub data: HashMap<String, CharacterProgressionComponent>,
+  pub privacy: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterProgressionComponent {
+  pub progressions: HashMap<String, Progression>,
+  pub factions: HashMap<String, FactionProgression>,
+  pub milestones: HashMap<String, Milestone>,
+  // Objectives of quest steps and the like that have no instance, by item hash.
+  #[serde(default)]
+  pub uninstanced_item_objectives: HashMap<String, Vec<ObjectiveProgress>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Progression {
+  pub progression_hash: u32,
+  pub daily_progress: i32,
+  pub daily_limit: i32,
+  pub weekly_progress: i32,
+  pub weekly_limit: i32,
+  pub current_progress: i32,
+  pub level: i32,
+  pub level_cap: i32,
+  pub step_index: usize,
+  pub progress_to_next_level: i32,
+  pub next_level_at: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FactionProgression {
+  pub faction_hash: u32,
+  pub progression_hash: u32,
+  pub daily_progress: i32,
+  pub daily_limit: i32,
+  pub weekly_progress: i32,
+  pub weekly_limit: i32,
+  pub current_progress: i32,
+  pub level: i32,
+  pub level_cap: i32,
+  pub step_index: usize,
+  pub progress_to_next_level: i32,
+  pub next_level_at: i32,
+}
+
+impl FactionProgression {
+  /// The faction's standing, as the progression it's built on.
+  pub fn progression(&self) -> Progression {
+    Progression {
+      progression_hash: self.progression_hash,
+      daily_progress: self.daily_progress,
+      daily_limit: self.daily_limit,
+      weekly_progress: self.weekly_progress,
+      weekly_limit: self.weekly_limit,
+      current_progress: self.current_progress,
+      level: self.level,
+      level_cap: self.level_cap,
+      step_index: self.step_index,
+      progress_to_next_level: self.progress_to_next_level,
+      next_level_at: self.next_level_at,
+    }
+  }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Milestone {
+  pub milestone_hash: u32,
+  #[serde(default)]
+  pub available_quests: Vec<MilestoneQuest>,
+  #[serde(default)]
+  pub activities: Vec<MilestoneActivity>,
+  pub end_date: Option<String>,
+}
+
+impl Milestone {
+  /// Every objective the milestone tracks, across its quests and activity
+  /// challenges.
+  pub fn objectives(&self) -> Vec<ObjectiveProgress> {
+    self.available_quests
+      .iter()
+      .filter_map(|quest| quest.status.clone())
+      .flat_map(|status| status.step_objectives)
+      .chain(self.activities
+        .iter()
+        .flat_map(|activity| activity.challenges.iter().map(|c| c.objective.clone())))
+      .collect()
+  }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MilestoneQuest {
+  pub quest_item_hash: u32,
+  pub status: Option<QuestStatus>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuestStatus {
+  pub completed: bool,
+  #[serde(default)]
+  pub step_objectives: Vec<ObjectiveProgress>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MilestoneActivity {
+  pub activity_hash: u32,
+  #[serde(default)]
+  pub challenges: Vec<ChallengeStatus>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeStatus {
+  pub objective: ObjectiveProgress,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectiveProgress {
+  pub objective_hash: u32,
+  pub progress: Option<i32>,
+  pub completion_value: i32,
+  pub complete: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressionDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  #[serde(default)]
+  pub steps: Vec<ProgressionStepDefinition>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressionStepDefinition {
+  pub step_name: Option<String>,
+  pub progress_total: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FactionDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  pub progression_hash: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MilestoneDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectiveDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  pub completion_value: i32,
+  pub progress_description: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KiosksComponentResponse {
+  pub data: KiosksComponent,
+  pub privacy: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterKiosksComponentResponse {
+  pub data: HashMap<String, KiosksComponent>,
+  pub privacy: i32,
+}
+
+// Kiosk items by the hash of the vendor that is the kiosk.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KiosksComponent {
+  pub kiosk_items: HashMap<String, Vec<KioskItem>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KioskItem {
+  // Into the kiosk vendor's item list.
+  pub index: usize,
+  pub can_acquire: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectibleDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  pub source_string: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SeasonDefinition {
+  pub hash: u32,
+  pub display_properties: DisplayProperties,
+  pub season_number: Option<i32>,
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::prelude::*;
+use hyper;
+use failure::ResultExt;
+
+use errors::*;
+use logging;
+
+/// Where API responses are dumped for debugging, and how many to keep.
+#[derive(Debug, Clone)]
+pub struct DumpConfig {
+  pub dir: PathBuf,
+  pub max_files: usize,
+  pub max_bytes: u64,
+}
+
+const TIMESTAMP: &str = "%Y%m%dT%H%M%S%.3f";
+
+lazy_static! {
+  static ref CONFIG: Mutex<Option<DumpConfig>> = Mutex::new(None);
+}
+
+/// Turns on dumping. Until this is called, responses aren't written anywhere.
+pub fn enable(cfg: DumpConfig) {
+  *CONFIG.lock().unwrap() = Some(cfg);
+}
+
+/// Writes the response body from `url` to the dump directory, if dumping is
+/// on, returning where it went. Failures are only logged; a dump is never
+/// worth failing a request over.
+pub fn record(url: &str, body: &hyper::Chunk) -> Option<PathBuf> {
+  let cfg = CONFIG.lock().unwrap().clone()?;
+  let path = cfg.dir.join(file_name(url));
+  match write(&path, body).and_then(|_| prune(&cfg)) {
+    Ok(()) => {
+      debug!("Wrote debug data to {:?}", path);
+      Some(path)
+    }
+    Err(e) => {
+      warn!("Error writing debug data: {}", e);
+      None
+    }
+  }
+}
+
+/// Removes every dump written so far, returning the files removed.
+pub fn clear() -> Result<Vec<PathBuf>> {
+  let cfg = match CONFIG.lock().unwrap().clone() {
+    Some(cfg) => cfg,
+    None => return Ok(Vec::new()),
+  };
+  let mut removed = Vec::new();
+  for (path, _) in dumps(&cfg.dir)? {
+    fs::remove_file(&path).with_context(|_| format!("removing {:?}", path))?;
+    removed.push(path);
+  }
+  Ok(removed)
+}
+
+// Timestamp first so names sort oldest first, then the endpoint, e.g.
+// 20181019T101500.123-Destiny2-2-Profile-4611686018.json
+fn file_name(url: &str) -> String {
+  let path = url
+    .parse::<hyper::Uri>()
+    .map(|uri| uri.path().to_owned())
+    .unwrap_or_default();
+  let endpoint = path
+    .split('/')
+    .filter(|segment| !segment.is_empty() && *segment != "Platform")
+    .map(|segment| {
+      segment
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect::<String>()
+    })
+    .collect::<Vec<_>>()
+    .join("-");
+  format!("{}-{}.json", Utc::now().format(TIMESTAMP), endpoint)
+}
+
+// Only names file_name could have made, so pruning and clearing leave alone
+// anything else that happens to be in the directory.
+fn is_dump(name: &str) -> bool {
+  let (stamp, rest) = match name.find('-') {
+    Some(dash) => name.split_at(dash),
+    None => return false,
+  };
+  rest.len() > "-.json".len()
+    && rest.ends_with(".json")
+    && NaiveDateTime::parse_from_str(stamp, TIMESTAMP).is_ok()
+}
+
+fn write(path: &Path, body: &hyper::Chunk) -> Result<()> {
+  let dir = path
+    .parent()
+    .ok_or(format_err!("Dump path has no dir (?!)"))?;
+  fs::create_dir_all(dir)?;
+  let mut file = fs::File::create(path)?;
+  Ok(write!(file, "{}", logging::redact(&String::from_utf8_lossy(&(*body))))?)
+}
+
+// Deletes the oldest dumps until we're within both limits.
+fn prune(cfg: &DumpConfig) -> Result<()> {
+  let mut dumps = dumps(&cfg.dir)?;
+  let mut total: u64 = dumps.iter().map(|&(_, size)| size).sum();
+  let mut count = dumps.len();
+  for (path, size) in dumps.drain(..) {
+    if count <= cfg.max_files && total <= cfg.max_bytes {
+      break;
+    }
+    fs::remove_file(&path).with_context(|_| format!("pruning {:?}", path))?;
+    count -= 1;
+    total -= size;
+  }
+  Ok(())
+}
+
+// The dumps in `dir`, oldest first, with their sizes.
+fn dumps(dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+  if !dir.is_dir() {
+    return Ok(Vec::new());
+  }
+  let mut dumps = fs::read_dir(dir)
+    .with_context(|_| format!("reading dump dir {:?}", dir))?
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| is_dump(&entry.file_name().to_string_lossy()))
+    .filter_map(|entry| {
+      entry
+        .metadata()
+        .ok()
+        .filter(|md| md.is_file())
+        .map(|md| (entry.path(), md.len()))
+    })
+    .collect::<Vec<_>>();
+  dumps.sort();
+  Ok(dumps)
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fs;
+use rusqlite::Connection;
+use failure::ResultExt;
+
+use errors::*;
+
+use super::dtos::{CharacterComponent, ItemResponse};
+
+/// Every inventory fetched, kept by account so runs can be compared. Lives
+/// next to the manifest cache.
+pub struct SnapshotStore {
+  db: Connection,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+  id: i64,
+  taken_at: String,
+  items: i64,
+}
+
+impl Snapshot {
+  pub fn id(&self) -> String {
+    format!("{}", self.id)
+  }
+
+  pub fn taken_at(&self) -> String {
+    self.taken_at.clone()
+  }
+
+  pub fn items(&self) -> String {
+    format!("{}", self.items)
+  }
+}
+
+#[derive(Debug, Clone)]
+struct SnapshotItem {
+  item_id: String,
+  item_name: String,
+  location: String,
+  power: i64,
+}
+
+/// One difference between two snapshots.
+#[derive(Debug, Clone)]
+pub struct Change {
+  change: &'static str,
+  item_name: String,
+  before: String,
+  after: String,
+}
+
+impl Change {
+  pub fn change(&self) -> String {
+    self.change.to_owned()
+  }
+
+  pub fn item_name(&self) -> String {
+    self.item_name.clone()
+  }
+
+  pub fn before(&self) -> String {
+    self.before.clone()
+  }
+
+  pub fn after(&self) -> String {
+    self.after.clone()
+  }
+}
+
+impl SnapshotStore {
+  pub fn open() -> Result<SnapshotStore> {
+    fs::create_dir_all(super::cache_dir()?)?;
+    let path = super::cache_path("history.sqlite")?;
+    let db = Connection::open(&path).with_context(|_| format!("opening history {:?}", path))?;
+    db.execute_batch(
+      "create table if not exists snapshots (
+         id integer primary key,
+         taken_at text not null default (datetime('now'))
+       );
+       create table if not exists snapshot_items (
+         snapshot_id integer not null references snapshots (id),
+         item_id text not null,
+         item_hash integer not null,
+         item_name text not null,
+         location text not null,
+         power integer not null
+       );
+       create index if not exists snapshot_items_snapshot on snapshot_items (snapshot_id);",
+    )?;
+    // Snapshots taken before they were kept by account belong to nobody, and
+    // so aren't shown to anyone.
+    let scoped = db
+      .prepare("pragma table_info(snapshots)")?
+      .query_map(&[], |row| row.get::<_, String>(1))?
+      .collect::<::std::result::Result<Vec<_>, _>>()?
+      .contains(&"membership_id".to_owned());
+    if !scoped {
+      db.execute_batch("alter table snapshots add column membership_id text not null default ''")?;
+    }
+    db.execute_batch("create index if not exists snapshots_membership on snapshots (membership_id);")?;
+    Ok(SnapshotStore { db })
+  }
+
+  /// Stores the instanced items in `items` as a new snapshot of the account
+  /// `membership_id`. Stackable things like materials have no instance to
+  /// follow, so they're left out.
+  pub fn record(
+    &mut self,
+    membership_id: &str,
+    items: &[ItemResponse],
+    characters: &[CharacterComponent],
+  ) -> Result<i64> {
+    let tx = self.db.transaction()?;
+    tx.execute("insert into snapshots (membership_id) values (?1)", &[&membership_id])?;
+    let id = tx.last_insert_rowid();
+    for item in items {
+      let item_id = match item.instance_id() {
+        Some(item_id) => item_id,
+        None => continue,
+      };
+      tx.execute(
+        "insert into snapshot_items (snapshot_id, item_id, item_hash, item_name, location, power)
+         values (?1, ?2, ?3, ?4, ?5, ?6)",
+        &[
+          &id,
+          &item_id,
+          &(item.item_hash()? as i64),
+          &item.item_name(),
+          &item.location(characters),
+          &(item.power() as i64),
+        ],
+      )?;
+    }
+    tx.commit()?;
+    Ok(id)
+  }
+
+  pub fn list(&self, membership_id: &str) -> Result<Vec<Snapshot>> {
+    let mut stmt = self.db.prepare(
+      "select s.id, s.taken_at, count(i.item_id)
+       from snapshots s left join snapshot_items i on i.snapshot_id = s.id
+       where s.membership_id = ?1
+       group by s.id order by s.id desc",
+    )?;
+    let rows = stmt.query_map(&[&membership_id], |row| Snapshot {
+      id: row.get(0),
+      taken_at: row.get(1),
+      items: row.get(2),
+    })?;
+    let snapshots = rows.collect::<::std::result::Result<Vec<_>, _>>()?;
+    Ok(snapshots)
+  }
+
+  /// What changed between the account's snapshot `since` - or the one
+  /// before its latest, if not given - and its latest snapshot.
+  pub fn diff(&self, membership_id: &str, since: Option<i64>) -> Result<Vec<Change>> {
+    let ids = self.list(membership_id)?.iter().map(|s| s.id).collect::<Vec<_>>();
+    let newest = *ids
+      .first()
+      .ok_or(format_err!("No snapshots yet - fetch the inventory to take one"))?;
+    let oldest = match since {
+      Some(id) if ids.contains(&id) => id,
+      Some(id) => bail!("No snapshot {}", id),
+      None => *ids
+        .get(1)
+        .ok_or(format_err!("Only one snapshot so far - fetch the inventory again to compare"))?,
+    };
+    Ok(compare(&self.items(oldest)?, &self.items(newest)?))
+  }
+
+  fn items(&self, snapshot_id: i64) -> Result<HashMap<String, SnapshotItem>> {
+    let mut stmt = self.db.prepare_cached(
+      "select item_id, item_name, location, power from snapshot_items where snapshot_id = ?1",
+    )?;
+    let rows = stmt.query_map(&[&snapshot_id], |row| SnapshotItem {
+      item_id: row.get(0),
+      item_name: row.get(1),
+      location: row.get(2),
+      power: row.get(3),
+    })?;
+    let mut items = HashMap::new();
+    for item in rows {
+      let item = item?;
+      items.insert(item.item_id.clone(), item);
+    }
+    Ok(items)
+  }
+}
+
+fn compare(before: &HashMap<String, SnapshotItem>, after: &HashMap<String, SnapshotItem>) -> Vec<Change> {
+  let mut changes = Vec::new();
+  for (id, new) in after {
+    match before.get(id) {
+      None => changes.push(Change {
+        change: "acquired",
+        item_name: new.item_name.clone(),
+        before: "".to_owned(),
+        after: format!("{} at {}", new.location, new.power),
+      }),
+      Some(old) => {
+        if old.location != new.location {
+          changes.push(Change {
+            change: "moved",
+            item_name: new.item_name.clone(),
+            before: old.location.clone(),
+            after: new.location.clone(),
+          });
+        }
+        if old.power != new.power {
+          changes.push(Change {
+            change: "power",
+            item_name: new.item_name.clone(),
+            before: format!("{}", old.power),
+            after: format!("{}", new.power),
+          });
+        }
+      }
+    }
+  }
+  for (id, old) in before {
+    if !after.contains_key(id) {
+      changes.push(Change {
+        change: "dismantled",
+        item_name: old.item_name.clone(),
+        before: format!("{} at {}", old.location, old.power),
+        after: "".to_owned(),
+      });
+    }
+  }
+  changes.sort_by(|left, right| {
+    (left.change, &left.item_name).cmp(&(right.change, &right.item_name))
+  });
+  changes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn item(id: &str, name: &str, location: &str, power: i64) -> (String, SnapshotItem) {
+    let item = SnapshotItem {
+      item_id: id.to_owned(),
+      item_name: name.to_owned(),
+      location: location.to_owned(),
+      power: power,
+    };
+    (id.to_owned(), item)
+  }
+
+  fn summary(changes: Vec<Change>) -> Vec<(String, String, String, String)> {
+    changes.into_iter().map(|c| (c.change(), c.item_name(), c.before(), c.after())).collect()
+  }
+
+  fn row(change: &str, name: &str, before: &str, after: &str) -> (String, String, String, String) {
+    (change.to_owned(), name.to_owned(), before.to_owned(), after.to_owned())
+  }
+
+  #[test]
+  fn reports_each_kind_of_change() {
+    let before = vec![
+      item("1", "Ace of Spades", "Vault", 1010),
+      item("2", "Gjallarhorn", "Hunter", 1020),
+      item("3", "Sunshot", "Vault", 1000),
+    ].into_iter().collect();
+    let after = vec![
+      item("1", "Ace of Spades", "Vault", 1010),
+      item("2", "Gjallarhorn", "Vault", 1030),
+      item("4", "Thorn", "Warlock", 1005),
+    ].into_iter().collect();
+    assert_eq!(
+      summary(compare(&before, &after)),
+      vec![
+        row("acquired", "Thorn", "", "Warlock at 1005"),
+        row("dismantled", "Sunshot", "Vault at 1000", ""),
+        row("moved", "Gjallarhorn", "Hunter", "Vault"),
+        row("power", "Gjallarhorn", "1020", "1030"),
+      ]
+    );
+  }
+
+  #[test]
+  fn unchanged_inventory_has_no_changes() {
+    let items: HashMap<_, _> = vec![item("1", "Ace of Spades", "Vault", 1010)].into_iter().collect();
+    assert!(compare(&items, &items).is_empty());
+  }
+}
@@ -0,0 +1,207 @@
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use errors::*;
+
+const SNIPPET_LEN: usize = 80;
+
+/// Like `serde_json::from_slice`, but a failure says where in the document
+/// it happened - e.g. `Response.characterEquipment.data.2305843009.items[14].state`
+/// - and quotes the start of the value found there.
+pub fn from_slice<T: DeserializeOwned>(json: &[u8]) -> Result<T> {
+  serde_json::from_slice(json).map_err(|e| {
+    let offset = offset_of(json, e.line(), e.column());
+    let (path, value_start) = path_at(json, offset);
+    format_err!("{} at {} near `{}`", e, path, snippet(json, value_start))
+  })
+}
+
+pub fn from_str<T: DeserializeOwned>(json: &str) -> Result<T> {
+  from_slice(json.as_bytes())
+}
+
+// serde_json counts lines and columns from 1; column 0 means the error was
+// found before the line's first character.
+fn offset_of(json: &[u8], line: usize, column: usize) -> usize {
+  let line_start = if line <= 1 {
+    0
+  } else {
+    json
+      .iter()
+      .enumerate()
+      .filter(|&(_, b)| *b == b'\n')
+      .nth(line - 2)
+      .map_or(json.len(), |(i, _)| i + 1)
+  };
+  (line_start + column.saturating_sub(1)).min(json.len())
+}
+
+enum Frame {
+  Object { key: Option<String>, in_value: bool },
+  Array { index: usize },
+}
+
+// Scans `json` up to `offset`, returning the path to the value being read
+// there and where that value starts.
+fn path_at(json: &[u8], offset: usize) -> (String, usize) {
+  let mut stack: Vec<Frame> = Vec::new();
+  // Where each open object or array starts.
+  let mut starts: Vec<usize> = Vec::new();
+  let mut value_start = 0;
+  let mut i = 0;
+
+  // serde_json points at the last character it read. When that closes an
+  // object or array - a missing field, a short tuple - the error is about
+  // the whole of it, not the last value inside.
+  let end = match json.get(offset) {
+    Some(&b'}') | Some(&b']') => offset + 1,
+    _ => offset,
+  };
+
+  while i < end {
+    match json[i] {
+      b'{' | b'[' => {
+        value_start = i;
+        starts.push(i);
+        stack.push(if json[i] == b'{' {
+          Frame::Object {
+            key: None,
+            in_value: false,
+          }
+        } else {
+          Frame::Array { index: 0 }
+        });
+      }
+      b'}' | b']' => {
+        stack.pop();
+        value_start = starts.pop().unwrap_or(value_start);
+      }
+      b',' => match stack.last_mut() {
+        Some(&mut Frame::Array { ref mut index }) => *index += 1,
+        Some(&mut Frame::Object {
+          ref mut key,
+          ref mut in_value,
+        }) => {
+          *key = None;
+          *in_value = false;
+        }
+        None => (),
+      },
+      b':' => {
+        if let Some(&mut Frame::Object { ref mut in_value, .. }) = stack.last_mut() {
+          *in_value = true;
+        }
+      }
+      b'"' => {
+        let start = i;
+        i += 1;
+        while i < json.len() && json[i] != b'"' {
+          if json[i] == b'\\' {
+            i += 1;
+          }
+          i += 1;
+        }
+        match stack.last_mut() {
+          Some(&mut Frame::Object {
+            ref mut key,
+            in_value: false,
+          }) => *key = Some(String::from_utf8_lossy(&json[start + 1..i.min(json.len())]).into_owned()),
+          _ => value_start = start,
+        }
+      }
+      b if b.is_ascii_whitespace() => (),
+      _ => {
+        // A bare scalar: note where it starts and skip the rest of it.
+        value_start = i;
+        while i + 1 < end && !b",}] \t\r\n".contains(&json[i + 1]) {
+          i += 1;
+        }
+      }
+    }
+    i += 1;
+  }
+
+  let mut path = String::new();
+  for frame in &stack {
+    match *frame {
+      Frame::Object { key: Some(ref key), .. } => {
+        if !path.is_empty() {
+          path.push('.');
+        }
+        path.push_str(key);
+      }
+      Frame::Object { key: None, .. } => (),
+      Frame::Array { index } => path.push_str(&format!("[{}]", index)),
+    }
+  }
+  if path.is_empty() {
+    path.push_str("the top level");
+  }
+  (path, value_start)
+}
+
+fn snippet(json: &[u8], start: usize) -> String {
+  let end = (start + SNIPPET_LEN).min(json.len());
+  let mut text = String::from_utf8_lossy(&json[start..end])
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ");
+  if end < json.len() {
+    text.push('…');
+  }
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Where `marker` starts in `json`.
+  fn at(json: &str, marker: &str) -> usize {
+    json.find(marker).expect("marker in test JSON")
+  }
+
+  #[test]
+  fn path_through_nested_arrays() {
+    let json = r#"{"a": {"b": [1, [2, 3], {"c": [4, 5, XX]}]}}"#;
+    // serde_json reports the last character of the value it choked on.
+    let (path, start) = path_at(json.as_bytes(), at(json, "XX") + 1);
+    assert_eq!(path, "a.b[2].c[2]");
+    assert_eq!(start, at(json, "XX"));
+  }
+
+  #[test]
+  fn keys_with_escaped_quotes() {
+    let json = r#"{"say \"hi\"": {"n": XX}}"#;
+    let (path, _) = path_at(json.as_bytes(), at(json, "XX"));
+    assert_eq!(path, r#"say \"hi\".n"#);
+  }
+
+  #[test]
+  fn missing_field_reported_at_its_object() {
+    #[derive(Deserialize, Debug)]
+    struct Inner {
+      #[allow(dead_code)]
+      wanted: u32,
+    }
+    #[derive(Deserialize, Debug)]
+    struct Outer {
+      #[allow(dead_code)]
+      inner: Inner,
+    }
+
+    let err = from_str::<Outer>(r#"{"inner": {"other": 1}}"#).unwrap_err().to_string();
+    assert!(err.contains("missing field `wanted`"), "{}", err);
+    assert!(err.contains(" at inner near `{\"other\": 1}"), "{}", err);
+  }
+
+  #[test]
+  fn offsets_on_later_lines() {
+    let json = b"{\n  \"a\": 1,\n  \"b\": XX\n}";
+    assert_eq!(offset_of(json, 1, 1), 0);
+    assert_eq!(offset_of(json, 3, 8), 19);
+    assert_eq!(&json[19..21], b"XX");
+    // Past the end of the document is clamped to it.
+    assert_eq!(offset_of(json, 9, 1), json.len());
+  }
+}
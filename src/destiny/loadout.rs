@@ -0,0 +1,471 @@
+use std::fs;
+use hyper;
+use rusqlite::Connection;
+use serde_json;
+use failure::ResultExt;
+
+use errors::*;
+
+use super::dtos::{CharacterComponent, ItemResponse, ItemSetActionRequest, ItemTransferRequest};
+use super::dtos::enums::BungieMemberType;
+use super::urls;
+
+/// Named loadouts - the items each character had equipped when saved - kept
+/// by account in a database next to the manifest cache.
+pub struct LoadoutStore {
+  db: Connection,
+}
+
+#[derive(Debug, Clone)]
+pub struct SavedItem {
+  character_id: String,
+  character_label: String,
+  item_id: String,
+  item_hash: u32,
+  item_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadoutSummary {
+  name: String,
+  characters: i64,
+  items: i64,
+  saved_at: String,
+}
+
+impl LoadoutSummary {
+  pub fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  pub fn characters(&self) -> String {
+    format!("{}", self.characters)
+  }
+
+  pub fn items(&self) -> String {
+    format!("{}", self.items)
+  }
+
+  pub fn saved_at(&self) -> String {
+    self.saved_at.clone()
+  }
+}
+
+impl LoadoutStore {
+  pub fn open() -> Result<LoadoutStore> {
+    fs::create_dir_all(super::cache_dir()?)?;
+    let path = super::cache_path("loadouts.sqlite")?;
+    let db = Connection::open(&path).with_context(|_| format!("opening loadouts {:?}", path))?;
+    db.execute_batch(
+      "create table if not exists loadouts (
+         membership_id text not null default '',
+         name text not null,
+         character_id text not null,
+         character_label text not null,
+         item_id text not null,
+         item_hash integer not null,
+         item_name text not null,
+         saved_at text not null default (datetime('now'))
+       );
+       create index if not exists loadouts_name on loadouts (name);",
+    )?;
+    // Loadouts saved before they were kept by account belong to nobody, and
+    // so can't be listed, applied or deleted by anyone.
+    let scoped = db
+      .prepare("pragma table_info(loadouts)")?
+      .query_map(&[], |row| row.get::<_, String>(1))?
+      .collect::<::std::result::Result<Vec<_>, _>>()?
+      .contains(&"membership_id".to_owned());
+    if !scoped {
+      db.execute_batch("alter table loadouts add column membership_id text not null default ''")?;
+    }
+    db.execute_batch("create index if not exists loadouts_membership on loadouts (membership_id, name);")?;
+    Ok(LoadoutStore { db })
+  }
+
+  /// Replaces the account's loadout `name` with what's equipped right now,
+  /// returning how many items were saved.
+  pub fn save(
+    &mut self,
+    membership_id: &str,
+    name: &str,
+    items: &[ItemResponse],
+    characters: &[CharacterComponent],
+  ) -> Result<usize> {
+    let tx = self.db.transaction()?;
+    tx.execute(
+      "delete from loadouts where membership_id = ?1 and name = ?2",
+      &[&membership_id, &name],
+    )?;
+    let mut saved = 0;
+    for item in items.iter().filter(|item| item.is_equipped()) {
+      let (character_id, item_id) = match (item.character_id.clone(), item.instance_id()) {
+        (Some(character_id), Some(item_id)) => (character_id, item_id),
+        _ => continue,
+      };
+      let label = characters
+        .iter()
+        .find(|c| c.character_id == character_id)
+        .map_or(character_id.clone(), |c| c.label());
+      tx.execute(
+        "insert into loadouts
+           (membership_id, name, character_id, character_label, item_id, item_hash, item_name)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        &[
+          &membership_id,
+          &name,
+          &character_id,
+          &label,
+          &item_id,
+          &(item.item_hash()? as i64),
+          &item.item_name(),
+        ],
+      )?;
+      saved += 1;
+    }
+    if saved == 0 {
+      bail!("Nothing is equipped - not saving an empty loadout")
+    }
+    tx.commit()?;
+    Ok(saved)
+  }
+
+  pub fn list(&self, membership_id: &str) -> Result<Vec<LoadoutSummary>> {
+    let mut stmt = self.db.prepare(
+      "select name, count(distinct character_id), count(*), max(saved_at)
+       from loadouts where membership_id = ?1 group by name order by name",
+    )?;
+    let rows = stmt.query_map(&[&membership_id], |row| LoadoutSummary {
+      name: row.get(0),
+      characters: row.get(1),
+      items: row.get(2),
+      saved_at: row.get(3),
+    })?;
+    let summaries = rows.collect::<::std::result::Result<Vec<_>, _>>()?;
+    Ok(summaries)
+  }
+
+  pub fn load(&self, membership_id: &str, name: &str) -> Result<Vec<SavedItem>> {
+    let mut stmt = self.db.prepare(
+      "select character_id, character_label, item_id, item_hash, item_name
+       from loadouts where membership_id = ?1 and name = ?2 order by character_id, rowid",
+    )?;
+    let rows = stmt.query_map(&[&membership_id, &name], |row| SavedItem {
+      character_id: row.get(0),
+      character_label: row.get(1),
+      item_id: row.get(2),
+      item_hash: row.get::<_, i64>(3) as u32,
+      item_name: row.get(4),
+    })?;
+    let saved = rows.collect::<::std::result::Result<Vec<_>, _>>()?;
+    if saved.is_empty() {
+      bail!("No loadout named {:?}", name)
+    }
+    Ok(saved)
+  }
+
+  pub fn delete(&self, membership_id: &str, name: &str) -> Result<()> {
+    let deleted = self.db.execute(
+      "delete from loadouts where membership_id = ?1 and name = ?2",
+      &[&membership_id, &name],
+    )?;
+    if deleted == 0 {
+      bail!("No loadout named {:?}", name)
+    }
+    Ok(())
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Move {
+  ToVault {
+    item_hash: u32,
+    item_id: String,
+    from: String,
+  },
+  FromVault {
+    item_hash: u32,
+    item_id: String,
+    to: String,
+  },
+  Equip {
+    item_ids: Vec<String>,
+    character_id: String,
+  },
+  Blocked(String),
+}
+
+/// One API call needed to put a saved loadout back on, or a reason it can't.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Step {
+  character: String,
+  item_name: String,
+  action: Move,
+  status: String,
+}
+
+impl Step {
+  fn new(character: &str, item_name: &str, action: Move) -> Step {
+    let status = match action {
+      Move::Blocked(_) => "blocked",
+      _ => "planned",
+    };
+    Step {
+      character: character.to_owned(),
+      item_name: item_name.to_owned(),
+      action,
+      status: status.to_owned(),
+    }
+  }
+
+  pub fn character(&self) -> String {
+    self.character.clone()
+  }
+
+  pub fn item_name(&self) -> String {
+    self.item_name.clone()
+  }
+
+  pub fn action(&self) -> String {
+    match self.action {
+      Move::ToVault { .. } => "transfer to vault".to_owned(),
+      Move::FromVault { .. } => "pull from vault".to_owned(),
+      Move::Equip { ref item_ids, .. } => format!("equip {} items", item_ids.len()),
+      Move::Blocked(ref why) => why.clone(),
+    }
+  }
+
+  pub fn status(&self) -> String {
+    self.status.clone()
+  }
+
+  pub fn mark(&mut self, status: &str) {
+    self.status = status.to_owned();
+  }
+
+  /// The endpoint and JSON body for this step, or None if there's nothing
+  /// to send.
+  pub fn request(&self, membership_type: BungieMemberType) -> Result<Option<(hyper::Uri, String)>> {
+    let (url, body) = match self.action {
+      Move::ToVault {
+        item_hash,
+        ref item_id,
+        ref from,
+      } => (
+        urls::transfer_item()?,
+        serde_json::to_string(&ItemTransferRequest {
+          item_reference_hash: item_hash,
+          stack_size: 1,
+          transfer_to_vault: true,
+          item_id: item_id.clone(),
+          character_id: from.clone(),
+          membership_type,
+        })?,
+      ),
+      Move::FromVault {
+        item_hash,
+        ref item_id,
+        ref to,
+      } => (
+        urls::transfer_item()?,
+        serde_json::to_string(&ItemTransferRequest {
+          item_reference_hash: item_hash,
+          stack_size: 1,
+          transfer_to_vault: false,
+          item_id: item_id.clone(),
+          character_id: to.clone(),
+          membership_type,
+        })?,
+      ),
+      Move::Equip {
+        ref item_ids,
+        ref character_id,
+      } => (
+        urls::equip_items()?,
+        serde_json::to_string(&ItemSetActionRequest {
+          item_ids: item_ids.clone(),
+          character_id: character_id.clone(),
+          membership_type,
+        })?,
+      ),
+      Move::Blocked(_) => return Ok(None),
+    };
+    Ok(Some((url, body)))
+  }
+}
+
+/// Works out the transfers and equips that would put `saved` back on, given
+/// where everything is now. Items move between characters by way of the
+/// vault, and each character's equips go out as a single call at the end.
+pub fn plan(saved: &[SavedItem], items: &[ItemResponse], characters: &[CharacterComponent]) -> Vec<Step> {
+  let mut transfers = Vec::new();
+  let mut equips: Vec<(String, String, Vec<String>)> = Vec::new();
+
+  for wanted in saved {
+    let label = &wanted.character_label;
+    if !characters.iter().any(|c| c.character_id == wanted.character_id) {
+      transfers.push(Step::new(
+        label,
+        &wanted.item_name,
+        Move::Blocked("character no longer exists".to_owned()),
+      ));
+      continue;
+    }
+    let item = match items.iter().find(|i| i.instance_id().as_ref() == Some(&wanted.item_id)) {
+      Some(item) => item,
+      None => {
+        transfers.push(Step::new(
+          label,
+          &wanted.item_name,
+          Move::Blocked("item not found - dismantled?".to_owned()),
+        ));
+        continue;
+      }
+    };
+
+    match item.character_id {
+      Some(ref holder) if holder == &wanted.character_id => {
+        if item.is_equipped() {
+          continue;
+        }
+      }
+      Some(ref holder) => {
+        if item.is_equipped() {
+          transfers.push(Step::new(
+            label,
+            &wanted.item_name,
+            Move::Blocked("equipped on another character".to_owned()),
+          ));
+          continue;
+        }
+        transfers.push(Step::new(
+          label,
+          &wanted.item_name,
+          Move::ToVault {
+            item_hash: wanted.item_hash,
+            item_id: wanted.item_id.clone(),
+            from: holder.clone(),
+          },
+        ));
+        transfers.push(Step::new(
+          label,
+          &wanted.item_name,
+          Move::FromVault {
+            item_hash: wanted.item_hash,
+            item_id: wanted.item_id.clone(),
+            to: wanted.character_id.clone(),
+          },
+        ));
+      }
+      None => transfers.push(Step::new(
+        label,
+        &wanted.item_name,
+        Move::FromVault {
+          item_hash: wanted.item_hash,
+          item_id: wanted.item_id.clone(),
+          to: wanted.character_id.clone(),
+        },
+      )),
+    }
+
+    match equips.iter().position(|e| e.0 == wanted.character_id) {
+      Some(i) => equips[i].2.push(wanted.item_id.clone()),
+      None => equips.push((
+        wanted.character_id.clone(),
+        label.clone(),
+        vec![wanted.item_id.clone()],
+      )),
+    }
+  }
+
+  transfers.extend(equips.into_iter().map(|(character_id, label, item_ids)| {
+    Step::new(&label, "", Move::Equip { item_ids, character_id })
+  }));
+  transfers
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json;
+
+  use super::*;
+
+  fn character(id: &str) -> CharacterComponent {
+    let json = format!(r#"{{"characterId": "{}", "classType": 0, "light": 1000}}"#, id);
+    serde_json::from_str(&json).unwrap()
+  }
+
+  fn item(id: &str, holder: Option<&str>, equipped: bool) -> ItemResponse {
+    let holder = holder.map_or("null".to_owned(), |h| format!("\"{}\"", h));
+    let json = format!(
+      r#"{{
+        "characterId": {},
+        "item": {{"data": {{"itemHash": 1, "itemInstanceId": "{}", "quantity": 1,
+                            "bucketHash": 2, "state": 0}}, "privacy": 1}},
+        "instance": {{"data": {{"itemLevel": 1, "quality": 0, "isEquipped": {},
+                                "canEquip": true, "equipRequiredLevel": 0}}, "privacy": 1}}
+      }}"#,
+      holder, id, equipped
+    );
+    serde_json::from_str(&json).unwrap()
+  }
+
+  fn saved(character_id: &str, item_id: &str) -> SavedItem {
+    SavedItem {
+      character_id: character_id.to_owned(),
+      character_label: format!("char {}", character_id),
+      item_id: item_id.to_owned(),
+      item_hash: 1,
+      item_name: format!("item {}", item_id),
+    }
+  }
+
+  fn summary(steps: Vec<Step>) -> Vec<(String, String, String, String)> {
+    steps.into_iter().map(|s| (s.character(), s.item_name(), s.action(), s.status())).collect()
+  }
+
+  fn row(character: &str, item: &str, action: &str, status: &str) -> (String, String, String, String) {
+    (character.to_owned(), item.to_owned(), action.to_owned(), status.to_owned())
+  }
+
+  #[test]
+  fn nothing_to_do_when_already_equipped() {
+    let steps = plan(&[saved("a", "1")], &[item("1", Some("a"), true)], &[character("a")]);
+    assert!(steps.is_empty());
+  }
+
+  #[test]
+  fn moves_items_through_the_vault_then_equips() {
+    let steps = plan(
+      &[saved("a", "1"), saved("a", "2"), saved("a", "3")],
+      &[item("1", Some("b"), false), item("2", None, false), item("3", Some("a"), false)],
+      &[character("a"), character("b")],
+    );
+    assert_eq!(
+      summary(steps),
+      vec![
+        row("char a", "item 1", "transfer to vault", "planned"),
+        row("char a", "item 1", "pull from vault", "planned"),
+        row("char a", "item 2", "pull from vault", "planned"),
+        row("char a", "", "equip 3 items", "planned"),
+      ]
+    );
+  }
+
+  #[test]
+  fn blocks_what_cannot_be_moved() {
+    let steps = plan(
+      &[saved("gone", "1"), saved("a", "2"), saved("a", "3")],
+      &[item("1", None, false), item("3", Some("b"), true)],
+      &[character("a"), character("b")],
+    );
+    assert_eq!(
+      summary(steps),
+      vec![
+        row("char gone", "item 1", "character no longer exists", "blocked"),
+        row("char a", "item 2", "item not found - dismantled?", "blocked"),
+        row("char a", "item 3", "equipped on another character", "blocked"),
+      ]
+    );
+  }
+}
@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::PathBuf;
+use rusqlite::Connection;
+use serde::de::DeserializeOwned;
+use super::json_path;
+use failure::ResultExt;
+
+use errors::*;
+
+use super::dtos::{InventoryItemDefinition, SandboxPerkDefinition};
+
+// The world content database is named for its content hash, so pick the most
+// recently downloaded one rather than asking the API which is current.
+pub fn latest_db_path() -> Result<PathBuf> {
+  let dir = super::cache_dir()?;
+  let mut candidates = fs::read_dir(&dir)
+    .map_err(|e| {
+      debug!("Reading manifest cache {:?}: {}", dir, e);
+      Problem::ManifestMissing
+    })?
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| {
+      entry
+        .file_name()
+        .to_string_lossy()
+        .starts_with("world_sql_content")
+    })
+    .filter_map(|entry| {
+      entry
+        .metadata()
+        .and_then(|md| md.modified())
+        .ok()
+        .map(|modified| (modified, entry.path()))
+    })
+    .collect::<Vec<_>>();
+  candidates.sort();
+  candidates
+    .pop()
+    .map(|(_, path)| path)
+    .ok_or_else(|| {
+      debug!("No manifest database in {:?}", dir);
+      Problem::ManifestMissing.into()
+    })
+}
+
+/// Fails unless there are item definitions and one of them deserializes.
+pub fn check(db: &Connection) -> Result<()> {
+  let json: String = db.query_row(
+    "select json from DestinyInventoryItemDefinition limit 1",
+    &[],
+    |row| row.get(0),
+  )?;
+  json_path::from_str::<InventoryItemDefinition>(&json)
+    .with_context(|e| format!("parsing an item definition: {}", e))?;
+  Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct DefinitionMatch {
+  source: &'static str,
+  hash: u32,
+  name: String,
+  description: String,
+  tier: String,
+  item_type: String,
+  infusion_category: String,
+  plug_category: String,
+}
+
+impl DefinitionMatch {
+  fn from_item(def: InventoryItemDefinition) -> DefinitionMatch {
+    DefinitionMatch {
+      source: if def.plug.is_some() { "Plug" } else { "Item" },
+      hash: def.hash,
+      name: def.display_properties.name.unwrap_or_default(),
+      description: def.display_properties.description.unwrap_or_default(),
+      tier: format!("{:?}", def.inventory.tier_type),
+      item_type: def.item_type_display_name,
+      infusion_category: def.quality.map_or("".to_owned(), |q| {
+        format!(
+          "{} ({})",
+          q.infusion_category_name.unwrap_or_default(),
+          q.infusion_category_hash.unwrap_or(0)
+        )
+      }),
+      plug_category: def.plug.map_or("".to_owned(), |p| p.plug_category_identifier),
+    }
+  }
+
+  fn from_perk(def: SandboxPerkDefinition) -> DefinitionMatch {
+    DefinitionMatch {
+      source: "Perk",
+      hash: def.hash,
+      name: def.display_properties.name.unwrap_or_default(),
+      description: def.display_properties.description.unwrap_or_default(),
+      tier: "".to_owned(),
+      item_type: "".to_owned(),
+      infusion_category: "".to_owned(),
+      plug_category: "".to_owned(),
+    }
+  }
+
+  pub fn source(&self) -> String {
+    self.source.to_owned()
+  }
+
+  pub fn hash(&self) -> String {
+    format!("{}", self.hash)
+  }
+
+  pub fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  pub fn description(&self) -> String {
+    self.description.clone()
+  }
+
+  pub fn tier(&self) -> String {
+    self.tier.clone()
+  }
+
+  pub fn item_type(&self) -> String {
+    self.item_type.clone()
+  }
+
+  pub fn infusion_category(&self) -> String {
+    self.infusion_category.clone()
+  }
+
+  pub fn plug_category(&self) -> String {
+    self.plug_category.clone()
+  }
+}
+
+/// Finds item, plug and perk definitions whose name contains `term`, or whose
+/// hash is exactly `term` when it's numeric.
+pub fn search(db: &Connection, term: &str) -> Result<Vec<DefinitionMatch>> {
+  let mut found = search_table::<InventoryItemDefinition>(
+    db,
+    "DestinyInventoryItemDefinition",
+    term,
+    |def| def.display_properties.name.clone(),
+  )?.into_iter()
+    .map(DefinitionMatch::from_item)
+    .collect::<Vec<_>>();
+  found.extend(
+    search_table::<SandboxPerkDefinition>(
+      db,
+      "DestinySandboxPerkDefinition",
+      term,
+      |def| def.display_properties.name.clone(),
+    )?.into_iter()
+      .map(DefinitionMatch::from_perk),
+  );
+  Ok(found)
+}
+
+fn search_table<T>(
+  db: &Connection,
+  table: &str,
+  term: &str,
+  name: fn(&T) -> Option<String>,
+) -> Result<Vec<T>>
+where
+  T: DeserializeOwned,
+{
+  let jsons = match term.parse::<u32>() {
+    Ok(hash) => {
+      let mut stmt = db.prepare(&format!("select json from {} where id = ?1", table))?;
+      let rows = stmt.query_map(&[&(hash as i32)], |row| row.get::<_, String>(0))?;
+      let jsons = rows.collect::<::std::result::Result<Vec<_>, _>>()?;
+      jsons
+    }
+    Err(_) => {
+      // LIKE narrows the scan; the name check below keeps us from matching
+      // descriptions and identifiers.
+      let mut stmt = db.prepare(&format!("select json from {} where json like ?1", table))?;
+      let rows = stmt.query_map(&[&format!("%{}%", term)], |row| row.get::<_, String>(0))?;
+      let jsons = rows.collect::<::std::result::Result<Vec<_>, _>>()?;
+      jsons
+    }
+  };
+
+  let needle = term.to_lowercase();
+  let numeric = term.parse::<u32>().is_ok();
+  Ok(
+    jsons
+      .iter()
+      .filter_map(|json| match json_path::from_str::<T>(json) {
+        Ok(def) => Some(def),
+        Err(e) => {
+          debug!("Skipping {} row: {}", table, e);
+          None
+        }
+      })
+      .filter(|def| {
+        numeric || name(def).map_or(false, |n| n.to_lowercase().contains(&needle))
+      })
+      .collect(),
+  )
+}
@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+
+use super::dtos::{CharacterComponent, ItemResponse};
+use super::dtos::enums::ClassType;
+
+// Equipment bucket hashes, names, and whether they hold weapons.
+static SLOTS: &[(u32, &str, bool)] = &[
+  (1498876634, "Kinetic Weapons", true),
+  (2465295065, "Energy Weapons", true),
+  (953998645, "Power Weapons", true),
+  (3448274439, "Helmet", false),
+  (3551918588, "Gauntlets", false),
+  (14239492, "Chest Armor", false),
+  (20886954, "Leg Armor", false),
+  (1585787867, "Class Armor", false),
+];
+
+/// The item chosen for one slot of a character's highest-power loadout.
+#[derive(Debug, Clone)]
+pub struct SlotPick {
+  character: String,
+  slot: &'static str,
+  item: ItemResponse,
+  location: String,
+  action: String,
+}
+
+impl SlotPick {
+  pub fn character(&self) -> String {
+    self.character.clone()
+  }
+
+  pub fn slot(&self) -> String {
+    self.slot.to_owned()
+  }
+
+  pub fn item_name(&self) -> String {
+    self.item.item_name()
+  }
+
+  pub fn tier(&self) -> String {
+    self.item.tier()
+  }
+
+  pub fn power(&self) -> String {
+    self.item.stat_value()
+  }
+
+  pub fn location(&self) -> String {
+    self.location.clone()
+  }
+
+  pub fn action(&self) -> String {
+    self.action.clone()
+  }
+
+  /// The average power over every slot of each character's loadout, as the
+  /// game works it out: a slot with nothing to put in it counts as 0.
+  pub fn average_power(picks: &[SlotPick]) -> String {
+    let characters = picks.iter().map(|p| &p.character).collect::<HashSet<_>>().len();
+    if characters == 0 {
+      return "".to_owned();
+    }
+    let total: i32 = picks.iter().map(|p| p.item.power()).sum();
+    format!("avg {:.1}", total as f64 / (characters * SLOTS.len()) as f64)
+  }
+}
+
+/// Picks the highest-power item for every slot of each character, wherever
+/// the items are now, allowing one exotic weapon and one exotic armor piece.
+pub fn loadouts(characters: &[CharacterComponent], items: &[ItemResponse]) -> Vec<SlotPick> {
+  let mut characters = characters.to_vec();
+  characters.sort_by_key(|c| c.character_id.clone());
+
+  characters
+    .iter()
+    .flat_map(|character| {
+      let usable = items
+        .iter()
+        .filter(|item| {
+          let class = item.class_type();
+          class == character.class_type || class == ClassType::Unknown
+        })
+        .collect::<Vec<_>>();
+
+      let mut picks = best_set(&usable, true);
+      picks.extend(best_set(&usable, false));
+      picks
+        .into_iter()
+        .map(|(slot, item)| SlotPick {
+          character: character.label(),
+          slot: slot,
+          item: item.clone(),
+          location: item.location(&characters),
+          action: action(item, character),
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+// The best weapon or armor slots, trying each slot as the one exotic.
+fn best_set<'i>(
+  usable: &[&'i ItemResponse],
+  weapons: bool,
+) -> Vec<(&'static str, &'i ItemResponse)> {
+  let slots = SLOTS.iter().filter(|s| s.2 == weapons).collect::<Vec<_>>();
+  let best_in = |hash: u32, exotic: bool| {
+    usable
+      .iter()
+      .filter(|item| item.slot_hash() == hash && item.is_exotic() == exotic)
+      .max_by_key(|item| item.power())
+      .cloned()
+  };
+
+  let plain = slots.iter().map(|s| best_in(s.0, false)).collect::<Vec<_>>();
+  let mut best = plain.clone();
+  for (i, slot) in slots.iter().enumerate() {
+    if let Some(exotic) = best_in(slot.0, true) {
+      let mut candidate = plain.clone();
+      candidate[i] = Some(exotic);
+      if total(&candidate) > total(&best) {
+        best = candidate;
+      }
+    }
+  }
+
+  slots
+    .iter()
+    .zip(best.into_iter())
+    .filter_map(|(slot, item)| item.map(|item| (slot.1, item)))
+    .collect()
+}
+
+fn total(set: &[Option<&ItemResponse>]) -> i32 {
+  set.iter().map(|item| item.map_or(0, |i| i.power())).sum()
+}
+
+fn action(item: &ItemResponse, character: &CharacterComponent) -> String {
+  match item.character_id {
+    Some(ref id) if id == &character.character_id => "".to_owned(),
+    Some(_) => "transfer".to_owned(),
+    None => "pull from vault".to_owned(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json;
+
+  use super::*;
+
+  const KINETIC: u32 = 1498876634;
+  const ENERGY: u32 = 2465295065;
+  const POWER: u32 = 953998645;
+  const HELMET: u32 = 3448274439;
+
+  fn item(name: &str, slot: u32, power: i32, exotic: bool) -> ItemResponse {
+    let json = format!(
+      r#"{{
+        "instance": {{"data": {{"primaryStat": {{"statHash": 1, "value": {}}}, "itemLevel": 1,
+                                "quality": 0, "isEquipped": false, "canEquip": true,
+                                "equipRequiredLevel": 0}}, "privacy": 1}},
+        "itemDef": {{"hash": 1, "displayProperties": {{"name": "{}"}}, "itemTypeDisplayName": "",
+                     "itemType": 0, "itemSubType": 0, "investmentStats": [],
+                     "inventory": {{"maxStackSize": 1, "bucketTypeHash": {},
+                                    "isInstanceItem": true, "tierType": {}}}}}
+      }}"#,
+      power,
+      name,
+      slot,
+      if exotic { 6 } else { 5 }
+    );
+    serde_json::from_str(&json).unwrap()
+  }
+
+  fn names(set: Vec<(&'static str, &ItemResponse)>) -> Vec<(&'static str, String)> {
+    set.into_iter().map(|(slot, item)| (slot, item.item_name())).collect()
+  }
+
+  #[test]
+  fn uses_the_exotic_that_adds_the_most_power() {
+    let items = vec![
+      item("kinetic", KINETIC, 1000, false),
+      item("kinetic exotic", KINETIC, 1010, true),
+      item("energy", ENERGY, 1005, false),
+      item("energy exotic", ENERGY, 1030, true),
+      item("power", POWER, 1000, false),
+      item("helmet", HELMET, 1050, false),
+    ];
+    let usable = items.iter().collect::<Vec<_>>();
+    assert_eq!(
+      names(best_set(&usable, true)),
+      vec![
+        ("Kinetic Weapons", "kinetic".to_owned()),
+        ("Energy Weapons", "energy exotic".to_owned()),
+        ("Power Weapons", "power".to_owned()),
+      ]
+    );
+  }
+
+  #[test]
+  fn keeps_legendaries_when_no_exotic_is_better() {
+    let items = vec![
+      item("kinetic", KINETIC, 1010, false),
+      item("kinetic exotic", KINETIC, 1000, true),
+    ];
+    let usable = items.iter().collect::<Vec<_>>();
+    assert_eq!(names(best_set(&usable, true)), vec![("Kinetic Weapons", "kinetic".to_owned())]);
+  }
+
+  fn pick(character: &str, item: ItemResponse) -> SlotPick {
+    SlotPick {
+      character: character.to_owned(),
+      slot: "",
+      item,
+      location: "".to_owned(),
+      action: "".to_owned(),
+    }
+  }
+
+  #[test]
+  fn averages_over_every_slot_counting_empty_ones_as_zero() {
+    let picks = vec![
+      pick("Titan", item("kinetic", KINETIC, 1000, false)),
+      pick("Titan", item("helmet", HELMET, 1000, false)),
+      pick("Hunter", item("kinetic", KINETIC, 800, false)),
+    ];
+    assert_eq!(SlotPick::average_power(&picks[..2]), "avg 250.0");
+    assert_eq!(SlotPick::average_power(&picks), "avg 175.0");
+    assert_eq!(SlotPick::average_power(&[]), "");
+  }
+
+  #[test]
+  fn skips_empty_slots() {
+    let items = vec![item("helmet exotic", HELMET, 1020, true)];
+    let usable = items.iter().collect::<Vec<_>>();
+    assert!(best_set(&usable, true).is_empty());
+    assert_eq!(names(best_set(&usable, false)), vec![("Helmet", "helmet exotic".to_owned())]);
+  }
+}
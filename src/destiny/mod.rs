@@ -1,45 +1,703 @@
-use std::{env, fs, io::{self, Read, Write}, path::{Path, PathBuf}};
-use futures::{Stream, future::{self, Future, Shared, SharedItem}};
+use std::{env, fs, io::{self, Read, Write}, path::PathBuf, time::{Duration, Instant}};
+use futures::{stream, Stream, future::{self, Future, Shared, SharedItem}};
 use hyper::{self, header, Body, Chunk, client::{Client, HttpConnector, Request}};
 use hyper_tls::HttpsConnector;
 use tokio_core::reactor::{Core, Handle};
 use zip::read::ZipArchive;
 use rusqlite::Connection;
+use serde_json;
 
 use failure::ResultExt;
 
 use errors::*;
 
+use metrics;
 use table;
 
 mod urls;
 mod headers;
 mod dtos;
+mod manifest;
+mod wishlist;
+mod view;
+mod max_power;
+mod loadout;
+mod history;
+mod cache;
+mod dumps;
+mod json_path;
+mod audit;
+mod vendors;
+mod progress;
+mod pursuits;
+mod collections;
+
+pub use self::view::{parse_flag, TableOptions};
+pub use self::collections::CollectionFilter;
 
 use self::dtos::Deser;
 use self::dtos::enums;
 
-pub fn api_exchange(token: String, app_auth: String) -> Result<table::Table<dtos::ItemResponse>> {
+pub fn api_exchange(
+  token: String,
+  app_auth: String,
+  opts: &TableOptions,
+) -> Result<table::Table<dtos::ItemResponse>> {
+  let items = prepared_items(token, app_auth, opts)?;
+  Ok(opts.printer()?.with_items(items))
+}
+
+pub fn api_exchange_json(token: String, app_auth: String, opts: &TableOptions) -> Result<String> {
+  let summaries = prepared_items(token, app_auth, opts)?
+    .iter()
+    .map(|item| item.summary())
+    .collect::<Vec<_>>();
+  Ok(serde_json::to_string_pretty(&summaries)?)
+}
+
+// Streamed tables take their column widths from this many items.
+const STREAM_SAMPLE: usize = 25;
+
+/// Renders the inventory table as items arrive, handing each piece of text
+/// to `emit`. Rows come in the order items are fetched: the sort and grouping
+/// options don't apply.
+pub fn stream_inventory<F>(
+  token: String,
+  app_auth: String,
+  opts: &TableOptions,
+  format: table::Format,
+  mut emit: F,
+) -> Result<()>
+where
+  F: FnMut(String) -> Result<()>,
+{
+  let printer = opts.printer()?;
+  let wishlist = match opts.wishlist_path {
+    Some(ref path) => Some(wishlist::Wishlist::load(path)?),
+    None if opts.filter.roll_matches => bail!("Filtering on rolls needs a wishlist - set WISHLIST_PATH"),
+    None => None,
+  };
+  let mut sample = Vec::new();
+  let mut rows = None;
+
+  each_item(token, app_auth, |mut item| {
+    if let Some(ref wishlist) = wishlist {
+      item.roll = wishlist.score(&item);
+    }
+    if !opts.filter.accepts(&item) {
+      return Ok(());
+    }
+    if rows.is_none() {
+      sample.push(item);
+      if sample.len() >= STREAM_SAMPLE {
+        rows = Some(start_stream(&printer, &sample, format, &mut emit)?);
+      }
+      return Ok(());
+    }
+    match rows {
+      Some(ref rows) => emit(rows.row(&item)),
+      None => Ok(()),
+    }
+  })?;
+
+  if rows.is_none() {
+    start_stream(&printer, &sample, format, &mut emit)?;
+  }
+  Ok(())
+}
+
+fn start_stream<F>(
+  printer: &table::Printer<dtos::ItemResponse>,
+  sample: &[dtos::ItemResponse],
+  format: table::Format,
+  emit: &mut F,
+) -> Result<table::RowStream<dtos::ItemResponse>>
+where
+  F: FnMut(String) -> Result<()>,
+{
+  let rows = printer.stream(sample, format);
+  emit(rows.header())?;
+  for item in sample {
+    emit(rows.row(item))?;
+  }
+  Ok(rows)
+}
+
+pub fn max_power(token: String, app_auth: String) -> Result<table::Table<max_power::SlotPick>> {
+  let mut items = Vec::new();
+  let characters = each_item(token, app_auth, |item| Ok(items.push(item)))?;
+
+  Ok(
+    table::printer()
+      .field("Slot", max_power::SlotPick::slot)
+      .aggregate(|picks| format!("{} slots", picks.len()))
+      .field("Item Name", max_power::SlotPick::item_name)
+      .field("Item Tier", max_power::SlotPick::tier)
+      .field("Power", max_power::SlotPick::power)
+      .align(table::Align::Right)
+      .aggregate(max_power::SlotPick::average_power)
+      .field("Location", max_power::SlotPick::location)
+      .field("Action", max_power::SlotPick::action)
+      .group_by(max_power::SlotPick::character)
+      .with_items(max_power::loadouts(&characters, &items)),
+  )
+}
+
+pub fn perks(token: String, app_auth: String) -> Result<table::Table<dtos::PerkRow>> {
+  let mut items = fetch_items(token, app_auth)?;
+  TableOptions::default().sort(&mut items)?;
+  let rows = items
+    .iter()
+    .flat_map(|item| item.perk_rows())
+    .collect::<Vec<_>>();
+
+  Ok(
+    table::printer()
+      .field("Item Name", dtos::PerkRow::item_name)
+      .field("Socket", dtos::PerkRow::socket_index)
+      .align(table::Align::Right)
+      .field("Plug", dtos::PerkRow::plug_name)
+      .field("Plug Type", dtos::PerkRow::plug_type)
+      .field("Plug Tier", dtos::PerkRow::plug_tier)
+      .field("Category", dtos::PerkRow::category_id)
+      .field("Enabled", dtos::PerkRow::enabled)
+      .field("Alternatives", dtos::PerkRow::alternatives)
+      .max_width(60)
+      .with_items(rows),
+  )
+}
+
+/// Keeps each account's profile for `ttl` rather than fetching it for every
+/// request. Meant for the server, where one user hits several pages.
+pub fn cache_profiles(ttl: Duration) {
+  cache::enable(ttl)
+}
+
+pub fn forget_profile(token: &str) {
+  cache::forget(token)
+}
+
+/// Removes what we've written locally about the signed-in account: the API
+/// responses kept for debugging, and with `all`, the loadout and history
+/// databases too. Returns the paths removed.
+pub fn wipe_local_data(all: bool) -> Result<Vec<PathBuf>> {
+  let mut removed = dumps::clear()?;
+  // Where responses were always dumped before dumping was made optional.
+  let mut paths = vec![legacy_dump_dir()];
+  if all {
+    paths.push(cache_path("loadouts.sqlite")?);
+    paths.push(cache_path("history.sqlite")?);
+  }
+
+  for path in paths {
+    if path.is_dir() {
+      fs::remove_dir_all(&path).with_context(|_| format!("removing {:?}", path))?;
+    } else if path.exists() {
+      fs::remove_file(&path).with_context(|_| format!("removing {:?}", path))?;
+    } else {
+      continue;
+    }
+    removed.push(path);
+  }
+  Ok(removed)
+}
+
+/// Dumps every API response body into `dir` for debugging, keeping at most
+/// `max_files` files and `max_bytes` bytes of them.
+pub fn dump_responses(dir: PathBuf, max_files: usize, max_bytes: u64) {
+  dumps::enable(dumps::DumpConfig {
+    dir,
+    max_files,
+    max_bytes,
+  })
+}
+
+/// Starts auditing API responses and manifest definitions against the DTOs
+/// they're read into. See `schema_report`.
+pub fn audit_schema() {
+  audit::enable()
+}
+
+/// The fields Bungie sends that we don't model, and the modeled fields that
+/// came back absent or null, by struct - or None if auditing isn't on.
+pub fn schema_report() -> Option<table::Table<audit::Drift>> {
+  audit::report().map(|found| {
+    table::printer()
+      .field("Struct", audit::Drift::type_name)
+      .field("Field", audit::Drift::field)
+      .aggregate(|found| format!("{} fields", found.len()))
+      .field("Issue", audit::Drift::issue)
+      .field("Count", audit::Drift::count)
+      .align(table::Align::Right)
+      .field("Example Path", audit::Drift::example)
+      .with_items(found)
+  })
+}
+
+/// The Bungie.net account a token belongs to.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Account {
+  pub membership_id: String,
+  pub display_name: String,
+  pub unique_name: String,
+  pub memberships: Vec<String>,
+}
+
+pub fn account(token: String, app_auth: String) -> Result<Account> {
+  let mut core = Core::new()?;
+  let authd = AuthGetter::new(&core, token, app_auth);
+  let user = core.run(
+    authd
+      .get(urls::get_membership_data_for_current_user()?)
+      .and_then(|dl| dtos::UserResponseBody::deser(dl)),
+  )?.response;
+
+  Ok(Account {
+    membership_id: user.bungie_net_user.membership_id,
+    display_name: user.bungie_net_user.display_name,
+    unique_name: user.bungie_net_user.unique_name,
+    memberships: user
+      .destiny_memberships
+      .iter()
+      .map(|card| format!("{} ({:?})", card.display_name, card.membership_type))
+      .collect(),
+  })
+}
+
+/// Saves what each character has equipped now as the loadout `name` of the
+/// Bungie.net account `membership_id`, returning how many items it holds.
+pub fn save_loadout(token: String, app_auth: String, membership_id: &str, name: &str) -> Result<usize> {
+  let mut items = Vec::new();
+  let characters = each_item(token, app_auth, |item| Ok(items.push(item)))?;
+  loadout::LoadoutStore::open()?.save(membership_id, name, &items, &characters)
+}
+
+pub fn loadouts(membership_id: &str) -> Result<table::Table<loadout::LoadoutSummary>> {
+  let found = loadout::LoadoutStore::open()?.list(membership_id)?;
+  Ok(
+    table::printer()
+      .field("Loadout", loadout::LoadoutSummary::name)
+      .field("Characters", loadout::LoadoutSummary::characters)
+      .align(table::Align::Right)
+      .field("Items", loadout::LoadoutSummary::items)
+      .align(table::Align::Right)
+      .field("Saved At", loadout::LoadoutSummary::saved_at)
+      .with_items(found),
+  )
+}
+
+pub fn delete_loadout(membership_id: &str, name: &str) -> Result<()> {
+  loadout::LoadoutStore::open()?.delete(membership_id, name)
+}
+
+/// The moves that would put a saved loadout back on, worked out from where
+/// everything was when planned. Applying runs exactly these, so what's done
+/// is what was shown.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoadoutPlan {
+  pub name: String,
+  steps: Vec<loadout::Step>,
+}
+
+impl LoadoutPlan {
+  pub fn table(&self) -> table::Table<loadout::Step> {
+    steps_table(self.steps.clone())
+  }
+}
+
+/// Plans the moves that would put the account's loadout `name` back on.
+pub fn plan_loadout(token: String, app_auth: String, membership_id: &str, name: &str) -> Result<LoadoutPlan> {
+  let saved = loadout::LoadoutStore::open()?.load(membership_id, name)?;
+  // Plan against where things are now, not a cached profile that predates
+  // moves made in the game since.
+  cache::forget(&token);
+  let mut items = Vec::new();
+  let characters = each_item(token, app_auth, |item| Ok(items.push(item)))?;
+  Ok(LoadoutPlan {
+    name: name.to_owned(),
+    steps: loadout::plan(&saved, &items, &characters),
+  })
+}
+
+/// Makes the moves in `plan`. Steps run in order and stop at the first
+/// failure, since later equips usually depend on earlier transfers.
+pub fn apply_loadout(token: String, app_auth: String, plan: LoadoutPlan) -> Result<table::Table<loadout::Step>> {
+  let mut steps = plan.steps;
+  cache::forget(&token);
+  run_steps(token, app_auth, &mut steps)?;
+  Ok(steps_table(steps))
+}
+
+fn steps_table(steps: Vec<loadout::Step>) -> table::Table<loadout::Step> {
+  table::printer()
+    .field("Character", loadout::Step::character)
+    .field("Item Name", loadout::Step::item_name)
+    .field("Action", loadout::Step::action)
+    .field("Status", loadout::Step::status)
+    .with_items(steps)
+}
+
+fn run_steps(token: String, app_auth: String, steps: &mut [loadout::Step]) -> Result<()> {
   let mut core = Core::new()?;
-  let content_client = build_client(&core)?;
   let authd = AuthGetter::new(&core, token, app_auth);
+  let card = core.run(fetch_card(&authd)?)?;
+
+  let mut failed = false;
+  for step in steps.iter_mut() {
+    let (url, body) = match step.request(card.membership_type)? {
+      Some(request) => request,
+      None => continue,
+    };
+    if failed {
+      step.mark("skipped");
+      continue;
+    }
+    let sent = authd
+      .post(url, body)
+      .and_then(|dl| dtos::ActionResponseBody::deser(dl))
+      .and_then(|body| {
+        if body.error_code != 1 {
+          bail!("{}: {}", body.error_status, body.message)
+        }
+        Ok(())
+      });
+    match core.run(sent) {
+      Ok(()) => step.mark("done"),
+      Err(e) => {
+        error!("Loadout step failed: {}", e);
+        step.mark(&format!("failed: {}", e));
+        failed = true;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// The inventory snapshots taken of the Bungie.net account `membership_id`.
+pub fn snapshots(membership_id: &str) -> Result<table::Table<history::Snapshot>> {
+  let found = history::SnapshotStore::open()?.list(membership_id)?;
+  Ok(
+    table::printer()
+      .field("Snapshot", history::Snapshot::id)
+      .align(table::Align::Right)
+      .field("Taken At", history::Snapshot::taken_at)
+      .field("Items", history::Snapshot::items)
+      .align(table::Align::Right)
+      .with_items(found),
+  )
+}
+
+/// The items acquired, dismantled, moved or changed in power between the
+/// account's latest inventory snapshot and `since`, or the one before it.
+pub fn snapshot_diff(membership_id: &str, since: Option<i64>) -> Result<table::Table<history::Change>> {
+  let changes = history::SnapshotStore::open()?.diff(membership_id, since)?;
+  Ok(
+    table::printer()
+      .field("Change", history::Change::change)
+      .field("Item Name", history::Change::item_name)
+      .aggregate(|changes| format!("{} changes", changes.len()))
+      .field("Before", history::Change::before)
+      .field("After", history::Change::after)
+      .with_items(changes),
+  )
+}
+
+fn prepared_items(
+  token: String,
+  app_auth: String,
+  opts: &TableOptions,
+) -> Result<Vec<dtos::ItemResponse>> {
+  let mut items = Vec::new();
+  each_item(token, app_auth, |item| Ok(items.push(item)))?;
+
+  if let Some(ref path) = opts.wishlist_path {
+    let wishlist = wishlist::Wishlist::load(path)?;
+    for item in items.iter_mut() {
+      item.roll = wishlist.score(item);
+    }
+  } else if opts.filter.roll_matches {
+    bail!("Filtering on rolls needs a wishlist - set WISHLIST_PATH")
+  }
+
+  items.retain(|item| opts.filter.accepts(item));
+  opts.sort(&mut items)?;
+  Ok(items)
+}
+
+fn fetch_items(token: String, app_auth: String) -> Result<Vec<dtos::ItemResponse>> {
+  let mut items = Vec::new();
+  each_item(token, app_auth, |item| Ok(items.push(item)))?;
+  Ok(items)
+}
+
+// Calls `f` with each item as soon as it and its definitions are fetched,
+// and returns the characters the items were found on. An inventory fetched
+// from Bungie, rather than the cache, is also recorded as a snapshot.
+fn each_item<F>(
+  token: String,
+  app_auth: String,
+  mut f: F,
+) -> Result<Vec<dtos::CharacterComponent>>
+where
+  F: FnMut(dtos::ItemResponse) -> Result<()>,
+{
+  if let Some(profile) = cache::get(&token) {
+    debug!("Using cached profile");
+    for item in profile.items {
+      f(item)?;
+    }
+    return Ok(profile.characters);
+  }
+
+  let mut core = Core::new()?;
+  let content_client = build_client(&core)?;
+  let authd = AuthGetter::new(&core, token.clone(), app_auth);
 
   let database_path = fetch_db_path(&authd)?.shared();
   let database_stored = store_db(clone_unshare(&database_path), content_client)?.shared();
   let database_name = get_db_name(unshare(database_path))?.shared();
 
-  let user_card = fetch_card(&authd)?.shared();
+  let user = fetch_user(&authd)?.shared();
+  let user_card = clone_unshare(&user).and_then(|user| first_card(&user)).shared();
   let profile = fetch_profile(clone_unshare(&user_card), &authd)?.shared();
 
   let equipment_ids = extract_equipment_ids(clone_unshare(&profile));
   let inventory_ids = extract_inventory_ids(clone_unshare(&profile));
   let vault_ids = extract_vault_ids(clone_unshare(&profile));
+  let characters = extract_characters(clone_unshare(&profile));
 
   let urls = map_urls(unshare(user_card), equipment_ids, vault_ids, inventory_ids);
-  let items = fetch_all_items(&authd, urls, database_name, database_stored);
-  let work = table_format(items);
+  let items = fetch_item_stream(&authd, urls, database_name, database_stored);
 
-  Ok(core.run(work)?)
+  let mut fetched = Vec::new();
+  let membership_id = unshare(user).map(|user| user.bungie_net_user.membership_id.clone());
+  let ((_, characters), membership_id) = core.run(
+    items
+      .for_each(|item| {
+        fetched.push(item.clone());
+        f(item)
+      })
+      .join(characters)
+      .join(membership_id),
+  )?;
+  // History is a convenience; an inventory shouldn't fail over it.
+  match history::SnapshotStore::open()
+    .and_then(|mut store| store.record(&membership_id, &fetched, &characters))
+  {
+    Ok(id) => debug!("Recorded inventory snapshot {}", id),
+    Err(e) => warn!("Couldn't record inventory snapshot: {}", e),
+  }
+  cache::put(
+    &token,
+    &membership_id,
+    cache::Profile {
+      items: fetched,
+      characters: characters.clone(),
+    },
+  );
+  Ok(characters)
+}
+
+/// What each vendor is selling this week to each character: costs, and the
+/// perks rolled on the weapons and armor. `vendor` is a vendor hash, fetched
+/// on its own, or part of a vendor's name, e.g. "Xur".
+pub fn vendors(token: String, app_auth: String, vendor: Option<&str>) -> Result<table::Table<vendors::Sale>> {
+  let mut core = Core::new()?;
+  let authd = AuthGetter::new(&core, token, app_auth);
+  let db = open_manifest(&mut core, &authd)?;
+  let card = core.run(fetch_card(&authd)?)?;
+  let characters = core.run(fetch_characters(&card, &authd)?)?;
+
+  let hash = vendor.and_then(|v| v.parse::<u32>().ok());
+  let name = if hash.is_some() { None } else { vendor };
+  let responses = core.run(future::join_all(
+    characters
+      .iter()
+      .map(|character| fetch_vendors(&card, &character.character_id, hash, &authd))
+      .collect::<Result<Vec<_>>>()?,
+  ))?;
+
+  let mut sales = Vec::new();
+  for (character, response) in characters.iter().zip(responses.iter()) {
+    sales.extend(vendors::sales(character, response, name, &db)?);
+  }
+  sales.sort_by_key(|sale| (sale.vendor(), sale.category(), sale.character(), sale.item_name()));
+
+  Ok(
+    table::printer()
+      .field("Vendor", vendors::Sale::vendor)
+      .field("Character", vendors::Sale::character)
+      .field("Category", vendors::Sale::category)
+      .field("Item Name", vendors::Sale::item_name)
+      .aggregate(|sales| format!("{} items", sales.len()))
+      .field("Item Type", vendors::Sale::item_kind)
+      .field("Item Tier", vendors::Sale::tier)
+      .field("Cost", vendors::Sale::cost)
+      .field("Perks", vendors::Sale::perks)
+      .max_width(60)
+      .field("Available", vendors::Sale::available)
+      .field("Refreshes", vendors::Sale::refreshes)
+      .group_by(vendors::Sale::vendor)
+      .with_items(sales),
+  )
+}
+
+/// Each character's faction reputations, named progressions and
+/// milestones: rank, progress to the next rank, and daily and weekly limits.
+pub fn progress(token: String, app_auth: String) -> Result<table::Table<progress::Standing>> {
+  let mut core = Core::new()?;
+  let authd = AuthGetter::new(&core, token, app_auth);
+  let db = open_manifest(&mut core, &authd)?;
+  let card = core.run(fetch_card(&authd)?)?;
+  let profile = core.run(fetch_profile_components(
+    &card,
+    &authd,
+    &[enums::ComponentType::Characters, enums::ComponentType::CharacterProgressions],
+  )?)?;
+
+  let characters = profile.characters.ok_or(format_err!("No characters!"))?.data;
+  let progressions = profile
+    .character_progressions
+    .ok_or(format_err!("No progressions!"))?
+    .data;
+  let mut standings = Vec::new();
+  for (id, component) in &progressions {
+    if let Some(character) = characters.get(id) {
+      standings.extend(progress::standings(character, component, &db));
+    }
+  }
+  standings.sort_by_key(|s| (s.character(), s.kind(), s.name()));
+
+  Ok(
+    table::printer()
+      .field("Character", progress::Standing::character)
+      .field("Kind", progress::Standing::kind)
+      .field("Name", progress::Standing::name)
+      .aggregate(|standings| format!("{} tracked", standings.len()))
+      .field("Rank", progress::Standing::rank)
+      .field("Next Rank", progress::Standing::next_rank)
+      .align(table::Align::Right)
+      .field("Weekly", progress::Standing::weekly)
+      .align(table::Align::Right)
+      .field("Daily", progress::Standing::daily)
+      .align(table::Align::Right)
+      .field("Ends", progress::Standing::ends)
+      .group_by(progress::Standing::character)
+      .with_items(standings),
+  )
+}
+
+/// Objectives on bounties, quest steps, catalysts and trackers, with how far
+/// along each is and when it expires. With `incomplete`, only objectives
+/// still to be finished.
+pub fn pursuits(token: String, app_auth: String, incomplete: bool) -> Result<table::Table<pursuits::Pursuit>> {
+  let mut core = Core::new()?;
+  let authd = AuthGetter::new(&core, token, app_auth);
+  let db = open_manifest(&mut core, &authd)?;
+  let card = core.run(fetch_card(&authd)?)?;
+  let profile = core.run(fetch_profile_components(
+    &card,
+    &authd,
+    &[
+      enums::ComponentType::Characters,
+      enums::ComponentType::CharacterInventories,
+      enums::ComponentType::CharacterEquipment,
+      enums::ComponentType::ProfileInventories,
+      enums::ComponentType::CharacterProgressions,
+      enums::ComponentType::ItemObjectives,
+    ],
+  )?)?;
+
+  let mut found = pursuits::pursuits(&profile, &db);
+  if incomplete {
+    found.retain(|pursuit| !pursuit.is_complete());
+  }
+  found.sort_by_key(|p| (p.location(), p.item_kind(), p.item_name()));
+
+  Ok(
+    table::printer()
+      .field("Location", pursuits::Pursuit::location)
+      .field("Item Name", pursuits::Pursuit::item_name)
+      .aggregate(|found| format!("{} objectives", found.len()))
+      .field("Item Type", pursuits::Pursuit::item_kind)
+      .field("Objective", pursuits::Pursuit::objective)
+      .max_width(50)
+      .field("Progress", pursuits::Pursuit::progress)
+      .align(table::Align::Right)
+      .field("Done", pursuits::Pursuit::percent)
+      .align(table::Align::Right)
+      .field("Complete", pursuits::Pursuit::complete)
+      .field("Expires", pursuits::Pursuit::expires)
+      .group_by(pursuits::Pursuit::location)
+      .with_items(found),
+  )
+}
+
+/// The exotics and legendaries the account's kiosks, and each character's,
+/// could hand out, and whether each has been acquired.
+pub fn collections(
+  token: String,
+  app_auth: String,
+  filter: &CollectionFilter,
+) -> Result<table::Table<collections::Collectible>> {
+  let mut core = Core::new()?;
+  let authd = AuthGetter::new(&core, token, app_auth);
+  let db = open_manifest(&mut core, &authd)?;
+  let card = core.run(fetch_card(&authd)?)?;
+  let profile = core.run(fetch_profile_components(
+    &card,
+    &authd,
+    &[enums::ComponentType::Characters, enums::ComponentType::Kiosks],
+  )?)?;
+
+  let mut found = collections::collectibles(&profile, filter, &db);
+  found.sort_by_key(|c| (c.owner(), c.kiosk(), c.tier(), c.item_name()));
+
+  Ok(
+    table::printer()
+      .field("Owner", collections::Collectible::owner)
+      .field("Kiosk", collections::Collectible::kiosk)
+      .field("Item Name", collections::Collectible::item_name)
+      .aggregate(|found| {
+        let acquired = found.iter().filter(|c| c.is_acquired()).count();
+        format!("{}/{} acquired", acquired, found.len())
+      })
+      .field("Item Tier", collections::Collectible::tier)
+      .field("Item Type", collections::Collectible::item_kind)
+      .field("Source", collections::Collectible::source)
+      .max_width(50)
+      .field("Season", collections::Collectible::season)
+      .field("Status", collections::Collectible::status)
+      .group_by(collections::Collectible::owner)
+      .with_items(found),
+  )
+}
+
+/// Checks that the cached manifest database opens and its item definitions
+/// parse, as a readiness check.
+pub fn check_manifest() -> Result<()> {
+  let db = Connection::open(manifest::latest_db_path()?).context("opening DB connection")?;
+  manifest::check(&db)
+}
+
+pub fn search_manifest(term: &str) -> Result<table::Table<manifest::DefinitionMatch>> {
+  let db = Connection::open(manifest::latest_db_path()?).context("opening DB connection")?;
+  let found = manifest::search(&db, term)?;
+
+  Ok(
+    table::printer()
+      .field("Source", manifest::DefinitionMatch::source)
+      .field("Hash", manifest::DefinitionMatch::hash)
+      .align(table::Align::Right)
+      .field("Name", manifest::DefinitionMatch::name)
+      .field("Tier", manifest::DefinitionMatch::tier)
+      .field("Item Type", manifest::DefinitionMatch::item_type)
+      .field("Infusion Cat.", manifest::DefinitionMatch::infusion_category)
+      .field("Plug Category", manifest::DefinitionMatch::plug_category)
+      .field("Description", manifest::DefinitionMatch::description)
+      .max_width(60)
+      .with_items(found),
+  )
 }
 
 fn unshare<T>(
@@ -54,11 +712,22 @@ fn clone_unshare<T>(
   future.clone().map_err(|sherr| format_err!("{:?}", sherr))
 }
 
-fn cache_path(filename: &str) -> Result<PathBuf> {
+fn legacy_dump_dir() -> PathBuf {
+  let mut path = env::temp_dir();
+  path.push("d2tools");
+  path
+}
+
+fn cache_dir() -> Result<PathBuf> {
   let mut path = env::home_dir().ok_or(format_err!("Can't determine $HOME!"))?;
   path.push(".local");
   path.push("cache");
   path.push("d2tools");
+  Ok(path)
+}
+
+fn cache_path(filename: &str) -> Result<PathBuf> {
+  let mut path = cache_dir()?;
   path.push(filename);
   Ok(path)
 }
@@ -123,6 +792,7 @@ fn store_db(
         let dbpath = cache_path(&database_name_from_path(&urlpath)?)?;
         let urlstr = format!("https://www.bungie.net{}", *urlpath);
         error!("Expecting db at {:?}", dbpath);
+        metrics::manifest_lookup(dbpath.is_file());
         Ok(if !dbpath.is_file() {
           info!("DB not present - downloading...");
           Some(
@@ -150,21 +820,92 @@ fn get_db_name(
   }))
 }
 
-fn fetch_card(authd: &AuthGetter) -> Result<impl Future<Item = dtos::UserInfoCard, Error = Error>> {
+fn fetch_user(authd: &AuthGetter) -> Result<impl Future<Item = dtos::UserMembershipData, Error = Error>> {
   Ok(
     authd
       .get(urls::get_membership_data_for_current_user()?)
       .and_then(|dl| dtos::UserResponseBody::deser(dl))
-      .and_then(|urb| {
-        let res: Result<_> = match urb.response.destiny_memberships.get(0) {
-          Some(membership) => Ok(membership.clone()),
-          None => bail!("No memberships!"),
-        };
-        res
-      }),
+      .map(|urb| urb.response),
   )
 }
 
+fn fetch_card(authd: &AuthGetter) -> Result<impl Future<Item = dtos::UserInfoCard, Error = Error>> {
+  Ok(fetch_user(authd)?.and_then(|user| first_card(&user)))
+}
+
+fn first_card(user: &dtos::UserMembershipData) -> Result<dtos::UserInfoCard> {
+  match user.destiny_memberships.get(0) {
+    Some(membership) => Ok(membership.clone()),
+    None => bail!("No memberships!"),
+  }
+}
+
+// Downloads the manifest database if it isn't cached yet, and opens it.
+fn open_manifest(core: &mut Core, authd: &AuthGetter) -> Result<Connection> {
+  let content_client = build_client(core)?;
+  let database_path = fetch_db_path(authd)?.shared();
+  let database_stored = store_db(clone_unshare(&database_path), content_client)?;
+  let database_name = get_db_name(unshare(database_path))?;
+  let (name, _) = core.run(database_name.join(database_stored))?;
+  Ok(Connection::open(name).context("opening DB connection")?)
+}
+
+// Just the given components of the profile, for views that don't need the
+// whole inventory.
+fn fetch_profile_components(
+  card: &dtos::UserInfoCard,
+  authd: &AuthGetter,
+  components: &[enums::ComponentType],
+) -> Result<impl Future<Item = dtos::DestinyProfileResponse, Error = Error>> {
+  let url = urls::get_profile(card.membership_type, card.id()?, components)?;
+  Ok(
+    authd
+      .get(url)
+      .and_then(|dl| dtos::ProfileResponseBody::deser(dl))
+      .map(|body| body.response),
+  )
+}
+
+fn fetch_characters(
+  card: &dtos::UserInfoCard,
+  authd: &AuthGetter,
+) -> Result<impl Future<Item = Vec<dtos::CharacterComponent>, Error = Error>> {
+  Ok(
+    fetch_profile_components(card, authd, &[enums::ComponentType::Characters])?
+      .and_then(|profile| profile.characters.ok_or(format_err!("No characters!")))
+      .map(|chars| chars.data.values().cloned().collect::<Vec<_>>()),
+  )
+}
+
+// Every vendor's sales for one character, or just those of `vendor_hash`.
+fn fetch_vendors(
+  card: &dtos::UserInfoCard,
+  character_id: &str,
+  vendor_hash: Option<u32>,
+  authd: &AuthGetter,
+) -> Result<Box<Future<Item = dtos::DestinyVendorsResponse, Error = Error>>> {
+  let components = [
+    enums::ComponentType::Vendors,
+    enums::ComponentType::VendorCategories,
+    enums::ComponentType::VendorSales,
+    enums::ComponentType::ItemSockets,
+  ];
+  Ok(match vendor_hash {
+    Some(hash) => Box::new(
+      authd
+        .get(urls::get_vendor(card.membership_type, &card.membership_id, character_id, hash, &components)?)
+        .and_then(|dl| dtos::VendorResponseBody::deser(dl))
+        .map(|body| body.response.into_vendors()),
+    ),
+    None => Box::new(
+      authd
+        .get(urls::get_vendors(card.membership_type, &card.membership_id, character_id, &components)?)
+        .and_then(|dl| dtos::VendorsResponseBody::deser(dl))
+        .map(|body| body.response),
+    ),
+  })
+}
+
 fn fetch_profile<'g>(
   card: impl Future<Item = SharedItem<dtos::UserInfoCard>, Error = Error> + 'g,
   authd: &'g AuthGetter,
@@ -243,6 +984,19 @@ fn extract_inventory_ids(
     })
 }
 
+fn extract_characters(
+  profile: impl Future<Item = SharedItem<dtos::DestinyProfileResponse>, Error = Error>,
+) -> impl Future<Item = Vec<dtos::CharacterComponent>, Error = Error> {
+  profile
+    .and_then(|profile| {
+      (*profile)
+        .clone()
+        .characters
+        .ok_or(format_err!("No characters!"))
+    })
+    .map(|chars| chars.data.values().cloned().collect::<Vec<_>>())
+}
+
 fn extract_vault_ids(
   profile: impl Future<Item = SharedItem<dtos::DestinyProfileResponse>, Error = Error>,
 ) -> impl Future<Item = Vec<String>, Error = Error> {
@@ -295,74 +1049,49 @@ fn map_urls(
     })
 }
 
-fn fetch_all_items<'g>(
+fn fetch_item_stream<'g>(
   authd: &'g AuthGetter,
   urls: impl Future<Item = Vec<hyper::Uri>, Error = Error> + 'g,
   database_name: Shared<impl Future<Item = PathBuf, Error = Error> + 'g>,
   database_stored: Shared<impl Future<Error = Error> + 'g>,
-) -> impl Future<Item = Vec<dtos::ItemResponse>, Error = Error> + 'g {
-  urls.and_then(move |urls| {
-    future::join_all(
-      urls
-        .iter()
-        .map(|url| {
-          let database = clone_unshare(&database_name)
-            .join(clone_unshare(&database_stored))
-            .and_then(
-              |(name, _)| Ok(Connection::open((*name).clone()).context("opening DB connection")?),
-            );
-
-          authd
-            .get(url.clone())
-            .and_then(|dl| dtos::ItemResponseBody::deser(dl))
-            .map(|res| res.response)
-            .join(database)
-            .and_then(|(ref mut item, ref db)| {
-              item.fetch_component_defs(db);
-              let item: dtos::ItemResponse = item.clone();
-              Ok(item)
-            })
-        })
-        .collect::<Vec<_>>(),
-    )
-  })
-}
+) -> impl Stream<Item = dtos::ItemResponse, Error = Error> + 'g {
+  urls
+    .map(move |urls| {
+      stream::futures_unordered(
+        urls
+          .iter()
+          .map(|url| {
+            let database = clone_unshare(&database_name)
+              .join(clone_unshare(&database_stored))
+              .and_then(
+                |(name, _)| Ok(Connection::open((*name).clone()).context("opening DB connection")?),
+              );
 
-fn table_format(
-  items: impl Future<Item = Vec<dtos::ItemResponse>, Error = Error>,
-) -> impl Future<Item = table::Table<dtos::ItemResponse>, Error = Error> {
-  items
-    .map(|mut items| {
-      items.sort_by(|left, right| {
-        left
-          .infusion_category()
-          .cmp(&right.infusion_category())
-          .then(left.infusion_power().cmp(&right.infusion_power()).reverse())
-      });
-      items
-    })
-    .map(|populated_items| {
-      table::printer()
-        .field("", dtos::ItemResponse::holding_status)
-        .field("Bucket Name", dtos::ItemResponse::bucket_name)
-        .field("Item Name", dtos::ItemResponse::item_name)
-        .field("Item Tier", dtos::ItemResponse::tier)
-        .field("Item Kind", dtos::ItemResponse::item_kind)
-        .field("Infusion Power", dtos::ItemResponse::infusion_power)
-        .field("Effective Power", dtos::ItemResponse::stat_value)
-        .field("Infusion Cat.", dtos::ItemResponse::infusion_category)
-        .with_items(populated_items)
+            authd
+              .get(url.clone())
+              .and_then(|dl| dtos::ItemResponseBody::deser(dl))
+              .map(|res| res.response)
+              .join(database)
+              .and_then(|(ref mut item, ref db)| {
+                item.fetch_component_defs(db);
+                let item: dtos::ItemResponse = item.clone();
+                Ok(item)
+              })
+          })
+          .collect::<Vec<_>>(),
+      )
     })
+    .flatten_stream()
 }
 
 struct RequestAction {
+  attempts: u32,
   url: hyper::Uri,
   app_auth: String,
   token: String,
   client: Client<HttpsConnector<HttpConnector>, Body>,
 }
 
-use rand::{self, Rng};
 use tokio_retry::{strategy, Action, Retry};
 
 impl Action for RequestAction {
@@ -371,36 +1100,63 @@ impl Action for RequestAction {
   type Error = hyper::Error;
 
   fn run(&mut self) -> Self::Future {
+    self.attempts += 1;
+    if self.attempts > 1 {
+      metrics::api_retry();
+    }
     let mut req = Request::new(hyper::Method::Get, self.url.clone());
-    req
-      .headers_mut()
-      .set(headers::XApiKey::key(self.app_auth.clone()));
-    req.headers_mut().set(header::Accept::json());
-    req.headers_mut().set(header::Authorization(header::Bearer {
-      token: self.token.to_owned(),
-    }));
+    authorize(req.headers_mut(), &self.app_auth, &self.token);
     Box::new(self.client.request(req))
   }
 }
 
-type Download = (String, OsString, hyper::Chunk);
+fn authorize(headers: &mut hyper::Headers, app_auth: &str, token: &str) {
+  headers.set(headers::XApiKey::key(app_auth.to_owned()));
+  headers.set(header::Accept::json());
+  headers.set(header::Authorization(header::Bearer {
+    token: token.to_owned(),
+  }));
+}
+
+fn record_call<E>(result: &::std::result::Result<hyper::Response, E>, started: Instant) {
+  let status = match *result {
+    Ok(ref res) => res.status().as_u16().to_string(),
+    Err(_) => "error".to_owned(),
+  };
+  metrics::api_call(&status, started.elapsed());
+}
+
+fn check_status(result: hyper::Response) -> Result<hyper::Response> {
+  match result.status() {
+    hyper::StatusCode::Ok => Ok(result),
+    hyper::StatusCode::Unauthorized => {
+      // XXX need to actually scrub the old token.
+      error!("Unauthorized!");
+      Err(Problem::AuthRequired.into())
+    }
+    hyper::StatusCode::TooManyRequests => Err(Problem::Throttled.into()),
+    hyper::StatusCode::ServiceUnavailable => {
+      Err(Problem::BungieDown(result.status().to_string()).into())
+    }
+    _ => {
+      info!("Other status: {}", result.status());
+      bail!("Other status from API: {}", result.status())
+    }
+  }
+}
+
+// The URL a body came from, where it was dumped if it was, and the body.
+type Download = (String, Option<PathBuf>, hyper::Chunk);
 
 struct AuthGetter {
   handle: Handle,
   client: Client<HttpsConnector<HttpConnector>, Body>,
   token: String,
   app_auth: String,
-  json_dir: PathBuf,
 }
 
 impl AuthGetter {
   fn new(core: &Core, token: String, app_auth: String) -> AuthGetter {
-    let mut json_dir = env::temp_dir();
-    json_dir.push("d2tools");
-    json_dir.push(&rand::thread_rng()
-      .gen_ascii_chars()
-      .take(8)
-      .collect::<String>());
     let client = build_client(core).unwrap();
     let handle = core.handle();
     AuthGetter {
@@ -408,7 +1164,6 @@ impl AuthGetter {
       client,
       token,
       app_auth,
-      json_dir,
     }
   }
 
@@ -418,12 +1173,13 @@ impl AuthGetter {
       .take(5);
 
     let outurl = url.to_string();
-    let json_out = self.next_json_path();
+    let started = Instant::now();
 
     let retry = Retry::spawn(
       self.handle.clone(),
       backoff,
       RequestAction {
+        attempts: 0,
         url: url,
         app_auth: self.app_auth.clone(),
         token: self.token.clone(),
@@ -432,48 +1188,44 @@ impl AuthGetter {
     );
 
     retry
-      .map_err(|e| Error::from(Error::from(e).context("network error")))
-      .and_then(|result| {
-        match result.status() {
-          hyper::StatusCode::Ok => Ok(result),
-          hyper::StatusCode::Unauthorized => {
-            // XXX need to actually scrub the old token.
-            error!("Unauthorized!");
-            bail!("unauthorized - old token scrubbed, rerun.")
-          }
-          _ => {
-            info!("Other status: {}", result.status());
-            bail!("Other status from API: {}", result.status())
-          }
-        }
+      .then(move |result| {
+        record_call(&result, started);
+        result
       })
+      .map_err(|e| Error::from(Error::from(e).context("network error")))
+      .and_then(check_status)
       .and_then(|res| res.body().concat2().map_err(|e| Error::from(e)))
-      .and_then(move |body_chunk| Ok((outurl, json_out, body_chunk)))
+      .and_then(move |body_chunk| {
+        let dumped = dumps::record(&outurl, &body_chunk);
+        Ok((outurl, dumped, body_chunk))
+      })
   }
 
-  fn next_json_path(&self) -> OsString {
-    let mut path = self.json_dir.clone();
-    let key: String = rand::thread_rng().gen_ascii_chars().take(8).collect();
-    path.push(format!("debug-{}.json", key));
-    path.into_os_string()
-  }
-}
+  // Actions change game state, so unlike `get` these are never retried.
+  fn post(&self, url: hyper::Uri, body: String) -> impl Future<Item = Download, Error = Error> {
+    let outurl = url.to_string();
+    let started = Instant::now();
 
-use std::ffi::OsString;
-use std::fmt::Debug;
+    let mut req = Request::new(hyper::Method::Post, url);
+    authorize(req.headers_mut(), &self.app_auth, &self.token);
+    req.headers_mut().set(header::ContentType::json());
+    req.set_body(body);
 
-fn write_body<T: AsRef<Path> + Debug + Clone>(path: T, chunk: &hyper::Chunk) {
-  match maybe_write_body(path.clone(), chunk) {
-    Ok(_) => debug!("Wrote debug data to {:?}", path),
-    Err(e) => debug!("Error writing debug data: {:?}", e),
+    self
+      .client
+      .request(req)
+      .then(move |result| {
+        record_call(&result, started);
+        result
+      })
+      .map_err(|e| Error::from(Error::from(e).context("network error")))
+      .and_then(check_status)
+      .and_then(|res| res.body().concat2().map_err(|e| Error::from(e)))
+      .and_then(move |body_chunk| {
+        let dumped = dumps::record(&outurl, &body_chunk);
+        Ok((outurl, dumped, body_chunk))
+      })
   }
 }
-fn maybe_write_body<T: AsRef<Path> + Debug + Clone>(path: T, chunk: &hyper::Chunk) -> Result<()> {
-  let pc = path.clone();
-  let dir = pc.as_ref()
-    .parent()
-    .ok_or(format_err!("JSON tempfile has no dir (?!)"))?;
-  fs::create_dir_all(dir)?;
-  let mut file = fs::File::create(path)?;
-  Ok(write!(file, "{}", String::from_utf8_lossy(&(*chunk)))?)
-}
+
+use std::fmt::Debug;
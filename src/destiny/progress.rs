@@ -0,0 +1,159 @@
+use rusqlite::Connection;
+
+use super::dtos::{self, CharacterComponent, CharacterProgressionComponent, FactionDefinition,
+                  MilestoneDefinition, Progression, ProgressionDefinition};
+
+/// A character's standing in one progression, faction or milestone.
+#[derive(Debug, Clone)]
+pub struct Standing {
+  character: String,
+  kind: &'static str,
+  name: String,
+  rank: String,
+  next_rank: String,
+  weekly: String,
+  daily: String,
+  ends: String,
+}
+
+impl Standing {
+  pub fn character(&self) -> String {
+    self.character.clone()
+  }
+
+  pub fn kind(&self) -> String {
+    self.kind.to_owned()
+  }
+
+  pub fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  pub fn rank(&self) -> String {
+    self.rank.clone()
+  }
+
+  pub fn next_rank(&self) -> String {
+    self.next_rank.clone()
+  }
+
+  pub fn weekly(&self) -> String {
+    self.weekly.clone()
+  }
+
+  pub fn daily(&self) -> String {
+    self.daily.clone()
+  }
+
+  pub fn ends(&self) -> String {
+    self.ends.clone()
+  }
+}
+
+/// `character`'s factions, named progressions and milestones. Progressions
+/// the manifest gives no name are internal bookkeeping, and left out.
+pub fn standings(
+  character: &CharacterComponent,
+  component: &CharacterProgressionComponent,
+  db: &Connection,
+) -> Vec<Standing> {
+  let mut found = Vec::new();
+
+  for faction in component.factions.values() {
+    let def: Option<FactionDefinition> =
+      dtos::find_definition("DestinyFactionDefinition", faction.faction_hash, db);
+    if let Some(name) = def.and_then(|def| named(def.display_properties.name)) {
+      let steps = dtos::find_definition("DestinyProgressionDefinition", faction.progression_hash, db);
+      found.push(standing(character, "Faction", name, &faction.progression(), steps));
+    }
+  }
+
+  for progression in component.progressions.values() {
+    let def: Option<ProgressionDefinition> =
+      dtos::find_definition("DestinyProgressionDefinition", progression.progression_hash, db);
+    if let Some(name) = def.clone().and_then(|def| named(def.display_properties.name)) {
+      found.push(standing(character, "Progression", name, progression, def));
+    }
+  }
+
+  for milestone in component.milestones.values() {
+    let def: Option<MilestoneDefinition> =
+      dtos::find_definition("DestinyMilestoneDefinition", milestone.milestone_hash, db);
+    let name = match def.and_then(|def| named(def.display_properties.name)) {
+      Some(name) => name,
+      None => continue,
+    };
+    let objectives = milestone.objectives();
+    let complete = objectives.iter().filter(|o| o.complete).count();
+    found.push(Standing {
+      character: character.label(),
+      kind: "Milestone",
+      name,
+      rank: if !objectives.is_empty() && complete == objectives.len() {
+        "Complete".to_owned()
+      } else {
+        "".to_owned()
+      },
+      next_rank: if objectives.is_empty() {
+        "".to_owned()
+      } else {
+        format!("{}/{} objectives", complete, objectives.len())
+      },
+      weekly: "".to_owned(),
+      daily: "".to_owned(),
+      ends: milestone.end_date.clone().unwrap_or_default(),
+    });
+  }
+  found
+}
+
+fn named(name: Option<String>) -> Option<String> {
+  name.filter(|name| !name.trim().is_empty())
+}
+
+fn standing(
+  character: &CharacterComponent,
+  kind: &'static str,
+  name: String,
+  progression: &Progression,
+  def: Option<ProgressionDefinition>,
+) -> Standing {
+  let step = def
+    .and_then(|def| def.steps.get(progression.step_index).cloned())
+    .and_then(|step| named(step.step_name));
+  let level = if progression.level_cap > 0 {
+    format!("{}/{}", progression.level, progression.level_cap)
+  } else {
+    format!("{}", progression.level)
+  };
+  let rank = match step {
+    Some(step) => format!("{} ({})", step, level),
+    None => level,
+  };
+
+  Standing {
+    character: character.label(),
+    kind,
+    name,
+    rank,
+    next_rank: if progression.next_level_at > 0 {
+      format!("{}/{}", progression.progress_to_next_level, progression.next_level_at)
+    } else {
+      "".to_owned()
+    },
+    weekly: limited(progression.weekly_progress, progression.weekly_limit),
+    daily: limited(progression.daily_progress, progression.daily_limit),
+    ends: "".to_owned(),
+  }
+}
+
+// Progress against a limit, if there is one.
+fn limited(progress: i32, limit: i32) -> String {
+  if limit > 0 {
+    format!("{}/{}", progress, limit)
+  } else if progress > 0 {
+    format!("{}", progress)
+  } else {
+    "".to_owned()
+  }
+}
@@ -0,0 +1,157 @@
+use std::cmp;
+use std::collections::HashMap;
+use rusqlite::Connection;
+
+use super::dtos::{self, CharacterComponent, DestinyProfileResponse, InventoryItemDefinition, Item,
+                  ObjectiveDefinition, ObjectiveProgress};
+
+/// Progress on one objective of a bounty, quest step, catalyst or tracker.
+#[derive(Debug, Clone)]
+pub struct Pursuit {
+  location: String,
+  item: Option<InventoryItemDefinition>,
+  objective: Option<ObjectiveDefinition>,
+  progress: ObjectiveProgress,
+  expires: String,
+}
+
+impl Pursuit {
+  pub fn location(&self) -> String {
+    self.location.clone()
+  }
+
+  pub fn item_name(&self) -> String {
+    self.item
+      .clone()
+      .and_then(|def| def.display_properties.name)
+      .unwrap_or_default()
+  }
+
+  pub fn item_kind(&self) -> String {
+    self.item.clone().map_or("".to_owned(), |def| def.item_type_display_name)
+  }
+
+  pub fn objective(&self) -> String {
+    self.objective
+      .clone()
+      .and_then(|def| {
+        def.progress_description
+          .filter(|d| !d.is_empty())
+          .or(def.display_properties.name)
+      })
+      .unwrap_or_else(|| self.progress.objective_hash.to_string())
+  }
+
+  pub fn progress(&self) -> String {
+    format!("{}/{}", self.progress.progress.unwrap_or(0), self.progress.completion_value)
+  }
+
+  pub fn percent(&self) -> String {
+    format!("{}%", self.percent_done())
+  }
+
+  fn percent_done(&self) -> i32 {
+    if self.progress.complete {
+      return 100;
+    }
+    let needed = cmp::max(self.progress.completion_value, 1) as i64;
+    let done = cmp::max(self.progress.progress.unwrap_or(0), 0) as i64;
+    cmp::min(done * 100 / needed, 100) as i32
+  }
+
+  pub fn complete(&self) -> String {
+    let complete = if self.progress.complete { "yes" } else { "no" };
+    complete.to_owned()
+  }
+
+  pub fn expires(&self) -> String {
+    self.expires.clone()
+  }
+
+  pub fn is_complete(&self) -> bool {
+    self.progress.complete
+  }
+}
+
+/// Every objective on the items in `profile`: instanced ones from the item
+/// objectives component, and uninstanced quest steps from each character's
+/// progressions.
+pub fn pursuits(profile: &DestinyProfileResponse, db: &Connection) -> Vec<Pursuit> {
+  let characters = profile
+    .characters
+    .as_ref()
+    .map(|chars| chars.data.clone())
+    .unwrap_or_default();
+  let objectives = profile
+    .item_components
+    .objectives
+    .as_ref()
+    .map(|objectives| objectives.data.clone())
+    .unwrap_or_default();
+  let mut objective_defs = HashMap::new();
+  let mut found = Vec::new();
+
+  let mut held: Vec<(String, Item)> = Vec::new();
+  for inventories in profile.character_equipment.iter().chain(profile.character_inventories.iter()) {
+    for (id, inventory) in &inventories.data {
+      let location = label(&characters, id);
+      held.extend(inventory.items.iter().map(|item| (location.clone(), item.clone())));
+    }
+  }
+  if let Some(ref vault) = profile.profile_inventory {
+    held.extend(vault.data.items.iter().map(|item| ("Vault".to_owned(), item.clone())));
+  }
+
+  for (location, item) in held {
+    let progress = match item.item_instance_id.as_ref().and_then(|id| objectives.get(id)) {
+      Some(component) => component.objectives.clone(),
+      None => continue,
+    };
+    add(&mut found, &mut objective_defs, db, &location, item.item_hash, progress, item.expiration_date);
+  }
+
+  if let Some(ref progressions) = profile.character_progressions {
+    for (id, component) in &progressions.data {
+      let location = label(&characters, id);
+      for (hash, progress) in &component.uninstanced_item_objectives {
+        if let Ok(hash) = hash.parse() {
+          add(&mut found, &mut objective_defs, db, &location, hash, progress.clone(), None);
+        }
+      }
+    }
+  }
+  found
+}
+
+fn add(
+  found: &mut Vec<Pursuit>,
+  objective_defs: &mut HashMap<u32, Option<ObjectiveDefinition>>,
+  db: &Connection,
+  location: &str,
+  item_hash: u32,
+  progress: Vec<ObjectiveProgress>,
+  expires: Option<String>,
+) {
+  if progress.is_empty() {
+    return;
+  }
+  let item: Option<InventoryItemDefinition> =
+    dtos::find_definition("DestinyInventoryItemDefinition", item_hash, db);
+  for objective in progress {
+    let def = objective_defs
+      .entry(objective.objective_hash)
+      .or_insert_with(|| dtos::find_definition("DestinyObjectiveDefinition", objective.objective_hash, db))
+      .clone();
+    found.push(Pursuit {
+      location: location.to_owned(),
+      item: item.clone(),
+      objective: def,
+      progress: objective,
+      expires: expires.clone().unwrap_or_default(),
+    });
+  }
+}
+
+fn label(characters: &HashMap<String, CharacterComponent>, id: &str) -> String {
+  characters.get(id).map_or(id.to_owned(), |c| c.label())
+}
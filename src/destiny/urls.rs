@@ -49,3 +49,43 @@ pub fn get_item(m_type: super::dtos::enums::BungieMemberType,
       .build();
   build_url(&path)
 }
+
+pub fn transfer_item() -> Result<hyper::Uri> {
+  build_url("./Destiny2/Actions/Items/TransferItem/")
+}
+
+pub fn equip_items() -> Result<hyper::Uri> {
+  build_url("./Destiny2/Actions/Items/EquipItems/")
+}
+
+pub fn get_vendors(m_type: super::dtos::enums::BungieMemberType,
+                   dmid: &str,
+                   character_id: &str,
+                   components: &[enums::ComponentType])
+                   -> Result<hyper::Uri> {
+  let path =
+    UriTemplate::new("./Destiny2/{membershipType}/Profile/{destinyMembershipId}/Character/{characterId}/Vendors/{?components}")
+      .set("membershipType", m_type)
+      .set("destinyMembershipId", dmid)
+      .set("characterId", character_id)
+      .set("components", enums::component_list(components))
+      .build();
+  build_url(&path)
+}
+
+pub fn get_vendor(m_type: super::dtos::enums::BungieMemberType,
+                  dmid: &str,
+                  character_id: &str,
+                  vendor_hash: u32,
+                  components: &[enums::ComponentType])
+                  -> Result<hyper::Uri> {
+  let path =
+    UriTemplate::new("./Destiny2/{membershipType}/Profile/{destinyMembershipId}/Character/{characterId}/Vendors/{vendorHash}/{?components}")
+      .set("membershipType", m_type)
+      .set("destinyMembershipId", dmid)
+      .set("characterId", character_id)
+      .set("vendorHash", vendor_hash.to_string())
+      .set("components", enums::component_list(components))
+      .build();
+  build_url(&path)
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use rusqlite::Connection;
+
+use errors::*;
+
+use super::dtos::{self, CharacterComponent, DestinyVendorsResponse, InventoryItemDefinition,
+                  VendorDefinition};
+
+/// One item a vendor is selling to one character.
+#[derive(Debug, Clone)]
+pub struct Sale {
+  character: String,
+  vendor: String,
+  category: String,
+  item: Option<InventoryItemDefinition>,
+  quantity: i32,
+  costs: Vec<(String, i32)>,
+  perks: Vec<String>,
+  available: bool,
+  refreshes: String,
+}
+
+impl Sale {
+  pub fn character(&self) -> String {
+    self.character.clone()
+  }
+
+  pub fn vendor(&self) -> String {
+    self.vendor.clone()
+  }
+
+  pub fn category(&self) -> String {
+    self.category.clone()
+  }
+
+  pub fn item_name(&self) -> String {
+    let name = self.item
+      .clone()
+      .and_then(|def| def.display_properties.name)
+      .unwrap_or_default();
+    if self.quantity > 1 {
+      format!("{} x{}", name, self.quantity)
+    } else {
+      name
+    }
+  }
+
+  pub fn item_kind(&self) -> String {
+    self.item.clone().map_or("".to_owned(), |def| def.item_type_display_name)
+  }
+
+  pub fn tier(&self) -> String {
+    self.item.clone().map_or("".to_owned(), |def| format!("{:?}", def.inventory.tier_type))
+  }
+
+  pub fn cost(&self) -> String {
+    self.costs
+      .iter()
+      .map(|&(ref currency, quantity)| format!("{} {}", quantity, currency))
+      .collect::<Vec<_>>()
+      .join(", ")
+  }
+
+  pub fn perks(&self) -> String {
+    self.perks.join(", ")
+  }
+
+  pub fn available(&self) -> String {
+    let available = if self.available { "yes" } else { "no" };
+    available.to_owned()
+  }
+
+  pub fn refreshes(&self) -> String {
+    self.refreshes.clone()
+  }
+}
+
+/// Everything on sale to `character` in `response`, from vendors whose name
+/// contains `name` if it's given.
+pub fn sales(
+  character: &CharacterComponent,
+  response: &DestinyVendorsResponse,
+  name: Option<&str>,
+  db: &Connection,
+) -> Result<Vec<Sale>> {
+  let vendors = match response.vendors {
+    Some(ref vendors) => &vendors.data,
+    None => return Ok(Vec::new()),
+  };
+  let mut found = Vec::new();
+  let mut names = Names::new(db);
+
+  for (hash, vendor) in vendors.iter().filter(|&(_, v)| v.enabled) {
+    // A vendor newer than our manifest is left out, rather than the rest
+    // going with it.
+    let def: VendorDefinition =
+      match dtos::find_definition("DestinyVendorDefinition", vendor.vendor_hash, db) {
+        Some(def) => def,
+        None => continue,
+      };
+    let vendor_name = def.display_properties.name.clone().unwrap_or_default();
+    if let Some(name) = name {
+      if !vendor_name.to_lowercase().contains(&name.to_lowercase()) {
+        continue;
+      }
+    }
+
+    let categories = category_names(&def, response, hash);
+    let sockets = response
+      .item_components
+      .get(hash)
+      .and_then(|components| components.sockets.clone())
+      .map(|sockets| sockets.data)
+      .unwrap_or_default();
+    let sale_items = response
+      .sales
+      .as_ref()
+      .and_then(|sales| sales.data.get(hash))
+      .map(|set| set.sale_items.values().cloned().collect::<Vec<_>>())
+      .unwrap_or_default();
+
+    for sale in sale_items {
+      let index = sale.vendor_item_index.to_string();
+      let perks = sockets
+        .get(&index)
+        .map(|socks| {
+          socks
+            .sockets
+            .iter()
+            .filter_map(|sock| sock.plug_hash)
+            .filter_map(|hash| names.item(hash))
+            .collect()
+        })
+        .unwrap_or_default();
+      let costs = sale
+        .costs
+        .iter()
+        .map(|cost| (names.item(cost.item_hash).unwrap_or_else(|| cost.item_hash.to_string()), cost.quantity))
+        .collect();
+
+      found.push(Sale {
+        character: character.label(),
+        vendor: vendor_name.clone(),
+        category: categories.get(&sale.vendor_item_index).cloned().unwrap_or_default(),
+        item: names.definition(sale.item_hash),
+        quantity: sale.quantity,
+        costs,
+        perks,
+        available: vendor.can_purchase && sale.sale_status == 0,
+        refreshes: vendor.next_refresh_date.clone(),
+      });
+    }
+  }
+  Ok(found)
+}
+
+// Vendor item index -> the name of the display category it's shown under.
+fn category_names(def: &VendorDefinition, response: &DestinyVendorsResponse, hash: &str) -> HashMap<i32, String> {
+  response
+    .categories
+    .as_ref()
+    .and_then(|categories| categories.data.get(hash))
+    .map(|component| {
+      component
+        .categories
+        .iter()
+        .flat_map(|category| {
+          let name = def
+            .display_categories
+            .get(category.display_category_index)
+            .and_then(|display| display.display_properties.name.clone())
+            .unwrap_or_default();
+          category.item_indexes.iter().map(move |index| (*index, name.clone()))
+        })
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+// Item definitions, looked up once each: vendors list the same currencies
+// and perks over and over.
+struct Names<'c> {
+  db: &'c Connection,
+  seen: HashMap<u32, Option<InventoryItemDefinition>>,
+}
+
+impl<'c> Names<'c> {
+  fn new(db: &'c Connection) -> Names<'c> {
+    Names {
+      db,
+      seen: HashMap::new(),
+    }
+  }
+
+  fn definition(&mut self, hash: u32) -> Option<InventoryItemDefinition> {
+    let db = self.db;
+    self.seen
+      .entry(hash)
+      .or_insert_with(|| dtos::find_definition("DestinyInventoryItemDefinition", hash, db))
+      .clone()
+  }
+
+  fn item(&mut self, hash: u32) -> Option<String> {
+    self.definition(hash)
+      .and_then(|def| def.display_properties.name)
+      .filter(|name| !name.is_empty())
+  }
+}
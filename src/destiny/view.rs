@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+
+use errors::*;
+
+use table;
+
+use super::dtos::ItemResponse;
+
+type Getter = fn(&ItemResponse) -> String;
+
+// Every column the inventory table can show: the name used to select or sort
+// on it, its header, how to get it, and whether it sorts as a number.
+static FIELDS: &[(&str, &str, Getter, bool)] = &[
+  ("status", "", ItemResponse::holding_status, false),
+  ("bucket", "Bucket Name", ItemResponse::bucket_name, false),
+  ("name", "Item Name", ItemResponse::item_name, false),
+  ("tier", "Item Tier", ItemResponse::tier, false),
+  ("kind", "Item Kind", ItemResponse::item_kind, false),
+  ("infusion_power", "Infusion Power", ItemResponse::infusion_power, true),
+  ("power", "Effective Power", ItemResponse::stat_value, true),
+  ("infusion_category", "Infusion Cat.", ItemResponse::infusion_category, true),
+  ("character", "Character", ItemResponse::character, false),
+  ("roll", "Roll", ItemResponse::roll, false),
+  ("mobility", "Mob", ItemResponse::mobility, true),
+  ("resilience", "Res", ItemResponse::resilience, true),
+  ("recovery", "Rec", ItemResponse::recovery, true),
+  ("impact", "Impact", ItemResponse::impact, true),
+  ("range", "Range", ItemResponse::range, true),
+  ("stability", "Stab", ItemResponse::stability, true),
+  ("handling", "Handling", ItemResponse::handling, true),
+  ("reload", "Reload", ItemResponse::reload_speed, true),
+  ("rpm", "RPM", ItemResponse::rounds_per_minute, true),
+  ("magazine", "Mag", ItemResponse::magazine, true),
+];
+
+static DEFAULT_COLUMNS: &[&str] = &[
+  "status",
+  "bucket",
+  "name",
+  "tier",
+  "kind",
+  "infusion_power",
+  "power",
+  "infusion_category",
+];
+
+static STAT_COLUMNS: &[&str] = &[
+  "mobility",
+  "resilience",
+  "recovery",
+  "impact",
+  "range",
+  "stability",
+  "handling",
+  "reload",
+  "rpm",
+  "magazine",
+];
+
+static DEFAULT_SORT: &[&str] = &["infusion_category", "-infusion_power"];
+
+// Options that are switched on by being present, rather than taking a value.
+static SWITCHES: &[&str] = &["stats", "locked", "rolls"];
+
+#[derive(Default, Debug)]
+pub struct TableOptions {
+  pub columns: Vec<String>,
+  pub with_stats: bool,
+  pub sort: Vec<String>,
+  pub group: Option<String>,
+  pub filter: ItemFilter,
+  pub wishlist_path: Option<String>,
+}
+
+#[derive(Default, Debug)]
+pub struct ItemFilter {
+  pub bucket: Option<String>,
+  pub tier: Option<String>,
+  pub kind: Option<String>,
+  pub min_power: Option<i32>,
+  pub locked_only: bool,
+  pub character: Option<String>,
+  pub roll_matches: bool,
+}
+
+impl TableOptions {
+  pub fn takes_value(name: &str) -> bool {
+    !SWITCHES.contains(&name)
+  }
+
+  /// Applies one named option, as given on the command line or in a query
+  /// string. Returns false if `name` isn't a table option at all.
+  pub fn set(&mut self, name: &str, value: &str) -> Result<bool> {
+    match name {
+      "columns" => self.columns.extend(split_list(value)),
+      "stats" => self.with_stats = parse_flag(name, value)?,
+      "sort" => self.sort = split_list(value),
+      "group" => self.group = Some(value.to_owned()),
+      "bucket" => self.filter.bucket = Some(value.to_owned()),
+      "tier" => self.filter.tier = Some(value.to_owned()),
+      "kind" => self.filter.kind = Some(value.to_owned()),
+      "min_power" => {
+        self.filter.min_power = Some(value
+          .parse()
+          .map_err(|_| Problem::BadRequest(format!("min_power {:?} isn't a number", value)))?)
+      }
+      "locked" => self.filter.locked_only = parse_flag(name, value)?,
+      "character" => self.filter.character = Some(value.to_owned()),
+      "rolls" => self.filter.roll_matches = parse_flag(name, value)?,
+      _ => return Ok(false),
+    }
+    Ok(true)
+  }
+
+  fn column_keys(&self) -> Vec<String> {
+    let mut keys = if self.columns.is_empty() {
+      let mut keys = to_strings(DEFAULT_COLUMNS);
+      if self.wishlist_path.is_some() {
+        keys.push("roll".to_owned());
+      }
+      keys
+    } else {
+      self.columns.clone()
+    };
+    if self.with_stats {
+      keys.extend(to_strings(STAT_COLUMNS));
+    }
+    keys
+  }
+
+  pub fn printer(&self) -> Result<table::Printer<ItemResponse>> {
+    let mut printer = table::printer();
+    for key in self.column_keys() {
+      let &(key, header, get, numeric) = field(&key)?;
+      printer = printer.keyed_field(key, header, get);
+      if numeric {
+        printer = printer.align(table::Align::Right);
+      }
+      match key {
+        "name" => printer = printer.aggregate(|items| format!("{} items", items.len())),
+        "power" | "infusion_power" => {
+          printer = printer.aggregate(move |items| {
+            items
+              .iter()
+              .filter_map(|item| get(item).parse::<i32>().ok())
+              .max()
+              .map_or("".to_owned(), |max| format!("max {}", max))
+          })
+        }
+        _ => (),
+      }
+    }
+    if let Some(ref key) = self.group {
+      let &(_, _, get, _) = field(key)?;
+      printer = printer.group_by(get);
+    }
+    Ok(printer)
+  }
+
+  pub fn sort(&self, items: &mut Vec<ItemResponse>) -> Result<()> {
+    let mut keys = if self.sort.is_empty() {
+      to_strings(DEFAULT_SORT)
+    } else {
+      self.sort.clone()
+    };
+    // Groups are only whole if their rows are adjacent.
+    if let Some(ref group) = self.group {
+      keys.insert(0, group.clone());
+    }
+    let keys = keys
+      .iter()
+      .map(|key| {
+        if key.starts_with('-') {
+          Ok((true, field(&key[1..])?))
+        } else {
+          Ok((false, field(key)?))
+        }
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    items.sort_by(|left, right| {
+      keys.iter().fold(Ordering::Equal, |ord, &(descending, field)| {
+        ord.then_with(|| {
+          let ord = compare(field, left, right);
+          if descending {
+            ord.reverse()
+          } else {
+            ord
+          }
+        })
+      })
+    });
+    Ok(())
+  }
+}
+
+impl ItemFilter {
+  pub fn accepts(&self, item: &ItemResponse) -> bool {
+    contains(&self.bucket, &item.bucket_name()) && matches(&self.tier, &item.tier()) &&
+    contains(&self.kind, &item.item_kind()) &&
+    self.min_power.map_or(true, |min| item.power() >= min) &&
+    (!self.locked_only || item.is_locked()) && matches(&self.character, &item.character()) &&
+    (!self.roll_matches || item.roll_complete())
+  }
+}
+
+fn field(key: &str) -> Result<&'static (&'static str, &'static str, Getter, bool)> {
+  Ok(FIELDS.iter().find(|f| f.0 == key).ok_or_else(|| {
+    Problem::BadRequest(format!(
+      "Unknown column {:?} - try one of: {}",
+      key,
+      FIELDS.iter().map(|f| f.0).collect::<Vec<_>>().join(", ")
+    ))
+  })?)
+}
+
+fn compare(
+  &(_, _, get, numeric): &(&str, &str, Getter, bool),
+  left: &ItemResponse,
+  right: &ItemResponse,
+) -> Ordering {
+  if numeric {
+    get(left).parse::<i64>().ok().cmp(&get(right).parse::<i64>().ok())
+  } else {
+    get(left).cmp(&get(right))
+  }
+}
+
+fn contains(wanted: &Option<String>, value: &str) -> bool {
+  wanted.as_ref().map_or(true, |w| value.to_lowercase().contains(&w.to_lowercase()))
+}
+
+fn matches(wanted: &Option<String>, value: &str) -> bool {
+  wanted.as_ref().map_or(true, |w| value.to_lowercase() == w.to_lowercase())
+}
+
+/// An on/off option's value: given bare, as on the command line, or as
+/// 1/true/0/false in a query string.
+pub fn parse_flag(name: &str, value: &str) -> Result<bool> {
+  match value {
+    "" | "1" | "true" => Ok(true),
+    "0" | "false" => Ok(false),
+    other => Err(Problem::BadRequest(format!("{} {:?} - use true or false", name, other)).into()),
+  }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+  value
+    .split(',')
+    .map(|s| s.trim())
+    .filter(|s| !s.is_empty())
+    .map(|s| s.to_owned())
+    .collect()
+}
+
+fn to_strings(list: &[&str]) -> Vec<String> {
+  list.iter().map(|s| s.to_string()).collect()
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use toml;
+use failure::ResultExt;
+
+use errors::*;
+
+use super::dtos::ItemResponse;
+
+/// A shared list of desired rolls, read from TOML like:
+///
+/// ```toml
+/// [[roll]]
+/// item = 1234567890
+/// perks = ["Outlaw", "Rampage", "3400784728"]
+/// ```
+///
+/// Perks may be named or given by plug hash.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Wishlist {
+  #[serde(default, rename = "roll")]
+  rolls: Vec<Roll>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Roll {
+  item: u32,
+  perks: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RollMatch {
+  pub matched: usize,
+  pub wanted: usize,
+}
+
+impl RollMatch {
+  pub fn is_complete(&self) -> bool {
+    self.matched == self.wanted
+  }
+}
+
+impl Wishlist {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Wishlist> {
+    let path = path.as_ref();
+    let mut text = String::new();
+    fs::File::open(path)
+      .with_context(|_| format!("opening wishlist {:?}", path))?
+      .read_to_string(&mut text)?;
+    let wishlist: Wishlist = toml::from_str(&text).with_context(|_| format!("parsing wishlist {:?}", path))?;
+    // A roll with no perks would match every copy of its item, and rank
+    // as complete.
+    if let Some(roll) = wishlist.rolls.iter().find(|roll| roll.perks.is_empty()) {
+      bail!("Wishlist {:?} has a roll for item {} with no perks", path, roll.item)
+    }
+    Ok(wishlist)
+  }
+
+  /// The best match among the rolls listed for this item, if any are.
+  pub fn score(&self, item: &ItemResponse) -> Option<RollMatch> {
+    let hash = item.item_hash().ok()? as u32;
+    let plugs = item.available_plugs();
+
+    self
+      .rolls
+      .iter()
+      .filter(|roll| roll.item == hash)
+      .map(|roll| RollMatch {
+        matched: roll
+          .perks
+          .iter()
+          .filter(|perk| plugs.iter().any(|plug| perk_matches(perk, plug)))
+          .count(),
+        wanted: roll.perks.len(),
+      })
+      .max_by(|left, right| (left.matched * right.wanted).cmp(&(right.matched * left.wanted)))
+  }
+}
+
+fn perk_matches(perk: &str, &(hash, ref name): &(u32, String)) -> bool {
+  match perk.parse::<u32>() {
+    Ok(wanted) => wanted == hash,
+    Err(_) => perk.to_lowercase() == name.to_lowercase(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+  use std::io::Write;
+  use serde_json;
+
+  use super::*;
+  use super::super::dtos::ItemSocketState;
+
+  fn item(hash: u32, plugs: &[(u32, &str)]) -> ItemResponse {
+    let json = format!(
+      r#"{{"item": {{"data": {{"itemHash": {}, "quantity": 1, "bucketHash": 1, "state": 0}},
+                    "privacy": 1}}}}"#,
+      hash
+    );
+    let mut item: ItemResponse = serde_json::from_str(&json).unwrap();
+    item.plug_defs = plugs.iter().map(|&(hash, name)| socket(hash, name)).collect();
+    item
+  }
+
+  fn socket(hash: u32, name: &str) -> ItemSocketState {
+    let json = format!(
+      r#"{{"plugHash": {0}, "isEnabled": true,
+          "plugDef": {{"hash": {0}, "displayProperties": {{"name": "{1}"}},
+                       "itemTypeDisplayName": "", "itemType": 0, "itemSubType": 0,
+                       "investmentStats": [],
+                       "inventory": {{"maxStackSize": 1, "bucketTypeHash": 1,
+                                      "isInstanceItem": false, "tierType": 0}}}}}}"#,
+      hash, name
+    );
+    serde_json::from_str(&json).unwrap()
+  }
+
+  fn wishlist(text: &str) -> Wishlist {
+    toml::from_str(text).unwrap()
+  }
+
+  fn score(wishlist: &Wishlist, item: &ItemResponse) -> Option<(usize, usize)> {
+    wishlist.score(item).map(|m| (m.matched, m.wanted))
+  }
+
+  #[test]
+  fn matches_perks_by_name_or_hash() {
+    let list = wishlist("[[roll]]\nitem = 7\nperks = [\"outlaw\", \"3400784728\", \"Kill Clip\"]\n");
+    let item = item(7, &[(1, "Outlaw"), (3400784728, "Rampage"), (2, "Zen Moment")]);
+    assert_eq!(score(&list, &item), Some((2, 3)));
+  }
+
+  #[test]
+  fn picks_the_closest_roll() {
+    let list = wishlist(
+      "[[roll]]\nitem = 7\nperks = [\"Outlaw\", \"Kill Clip\", \"Zen Moment\"]\n\
+       [[roll]]\nitem = 7\nperks = [\"Rampage\"]\n",
+    );
+    let item = item(7, &[(1, "Outlaw"), (2, "Rampage")]);
+    assert_eq!(score(&list, &item), Some((1, 1)));
+  }
+
+  #[test]
+  fn ignores_unlisted_items() {
+    let list = wishlist("[[roll]]\nitem = 7\nperks = [\"Outlaw\"]\n");
+    assert_eq!(score(&list, &item(8, &[(1, "Outlaw")])), None);
+  }
+
+  #[test]
+  fn rejects_rolls_without_perks() {
+    let path = env::temp_dir().join(format!("wishlist-test-{}.toml", ::std::process::id()));
+    fs::File::create(&path).unwrap().write_all(b"[[roll]]\nitem = 7\nperks = []\n").unwrap();
+    let loaded = Wishlist::load(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(format!("{}", loaded.unwrap_err()).contains("item 7 with no perks"));
+  }
+}
@@ -1,2 +1,29 @@
 pub use failure::Error;
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// The failures a user can do something about, or at least should be told
+/// about plainly. Anything else is reported as an internal error.
+#[derive(Debug, Fail)]
+pub enum Problem {
+  #[fail(display = "You need to sign in with Bungie.net first")]
+  AuthRequired,
+  #[fail(display = "Bungie.net is throttling us - try again in a minute")]
+  Throttled,
+  #[fail(display = "Bungie.net is down or in maintenance ({})", _0)]
+  BungieDown(String),
+  #[fail(display = "The item manifest isn't downloaded yet - load the inventory once to fetch it")]
+  ManifestMissing,
+  #[fail(display = "The server is misconfigured: {}", _0)]
+  ConfigInvalid(String),
+  #[fail(display = "{}", _0)]
+  BadRequest(String),
+  #[fail(display = "That request came from another site - make it from this one")]
+  CrossSite,
+}
+
+impl Problem {
+  /// The first `Problem` among `err` and its causes, if there is one.
+  pub fn find(err: &Error) -> Option<&Problem> {
+    err.iter_chain().filter_map(|fail| fail.downcast_ref::<Problem>()).next()
+  }
+}
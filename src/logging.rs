@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::io;
+use std::str::FromStr;
+use std::sync::RwLock;
+use chrono::prelude::*;
+use fern;
+use log::LogLevelFilter;
+use serde_json;
+use failure::ResultExt;
+
+use errors::*;
+use state::AppConfig;
+
+const REDACTED: &str = "[redacted]";
+
+// Names whose values are always scrubbed when they appear as `name=value`,
+// `"name":"value"` or `name: "value"`, wherever the value came from.
+static SECRET_NAMES: &[&str] = &[
+  "code",
+  "state",
+  "token",
+  "access_token",
+  "refresh_token",
+  "client_secret",
+  "api_key",
+  "X-API-Key",
+];
+
+// Noisy dependencies, quietened unless LOG_TARGETS says otherwise.
+static QUIET_TARGETS: &[&str] = &[
+  "tokio_core",
+  "tokio_core::reactor",
+  "tokio_proto::streaming::pipeline::advanced",
+];
+
+// How many secrets seen at run time - OAuth codes and tokens - are kept.
+// Older ones are long out of use, and the name patterns still catch them.
+const MAX_SEEN: usize = 500;
+
+#[derive(Default)]
+struct Secrets {
+  // From the config, for as long as we run.
+  config: Vec<String>,
+  // Seen since, newest last.
+  seen: VecDeque<String>,
+}
+
+lazy_static! {
+  static ref SECRETS: RwLock<Secrets> = RwLock::new(Secrets::default());
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+  time: String,
+  level: String,
+  target: &'a str,
+  message: String,
+}
+
+/// Sets up logging from LOG_LEVEL, LOG_TARGETS, LOG_FILE and LOG_FORMAT.
+/// Console output goes to stderr when `stderr` is set, so command output on
+/// stdout stays clean. Every line is passed through `redact`.
+pub fn configure(cfg: &AppConfig, default_level: LogLevelFilter, stderr: bool) -> Result<()> {
+  {
+    let mut secrets = SECRETS.write().unwrap();
+    for value in &[&cfg.api_key, &cfg.client_secret, &cfg.access_token, &cfg.refresh_token] {
+      if worth_scrubbing(value) && !secrets.config.contains(value) {
+        secrets.config.push(value.to_string());
+      }
+    }
+  }
+
+  let level = if cfg.log_level.is_empty() {
+    default_level
+  } else {
+    parse_level(&cfg.log_level)?
+  };
+  let json = match cfg.log_format.as_str() {
+    "" | "text" => false,
+    "json" => true,
+    other => {
+      return Err(Problem::ConfigInvalid(format!("LOG_FORMAT {:?} - use text or json", other)).into())
+    }
+  };
+
+  let mut dispatch = fern::Dispatch::new()
+    .level(level)
+    .format(move |out, message, record| {
+      let message = redact(&message.to_string());
+      if json {
+        let line = JsonLine {
+          time: Utc::now().to_rfc3339(),
+          level: record.level().to_string(),
+          target: record.target(),
+          message,
+        };
+        out.finish(format_args!("{}", serde_json::to_string(&line).unwrap_or_default()))
+      } else {
+        out.finish(format_args!(
+          "[{}] {}[{}] {}",
+          Utc::now().format("%Y-%m-%d %H:%M:%S%.9f"),
+          record.target(),
+          record.level(),
+          message
+        ))
+      }
+    });
+
+  for target in QUIET_TARGETS {
+    dispatch = dispatch.level_for(*target, LogLevelFilter::Error);
+  }
+  for pair in cfg.log_targets.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+    let mut parts = pair.splitn(2, '=');
+    let target = parts.next().unwrap_or_default().to_owned();
+    let level = parts.next().ok_or_else(|| {
+      Problem::ConfigInvalid(format!("LOG_TARGETS entry {:?} - use target=level", pair))
+    })?;
+    dispatch = dispatch.level_for(target, parse_level(level)?);
+  }
+
+  dispatch = if stderr {
+    dispatch.chain(io::stderr())
+  } else {
+    dispatch.chain(io::stdout())
+  };
+  if !cfg.log_file.is_empty() {
+    dispatch = dispatch.chain(
+      fern::log_file(&cfg.log_file).with_context(|_| format!("opening LOG_FILE {:?}", cfg.log_file))?,
+    );
+  }
+  Ok(dispatch.apply()?)
+}
+
+/// Marks a value, like a token or OAuth code, as never to be written out.
+pub fn secret(value: &str) {
+  if !worth_scrubbing(value) {
+    return;
+  }
+  let mut secrets = SECRETS.write().unwrap();
+  if !secrets.seen.iter().any(|s| s == value) {
+    secrets.seen.push_back(value.to_owned());
+  }
+  while secrets.seen.len() > MAX_SEEN {
+    secrets.seen.pop_front();
+  }
+}
+
+/// Stops scrubbing `value`, once it's been revoked or thrown away.
+pub fn forget_secret(value: &str) {
+  SECRETS.write().unwrap().seen.retain(|s| s != value);
+}
+
+// Short values would scrub half of every line.
+fn worth_scrubbing(value: &str) -> bool {
+  value.len() >= 8
+}
+
+/// Scrubs known secrets and anything that looks like a credential from
+/// `text`. Used for log lines and for errors we print.
+pub fn redact(text: &str) -> String {
+  let mut text = text.to_owned();
+  {
+    let secrets = SECRETS.read().unwrap();
+    for value in secrets.config.iter().chain(secrets.seen.iter()) {
+      text = text.replace(value.as_str(), REDACTED);
+    }
+  }
+  text = scrub_after(&text, "Bearer ");
+  for name in SECRET_NAMES {
+    for separator in &["=", "\":\"", "\": \"", ": \""] {
+      text = scrub_after(&text, &format!("{}{}", name, separator));
+    }
+  }
+  text
+}
+
+// Replaces the value following each occurrence of `prefix`, up to the next
+// delimiter.
+fn scrub_after(text: &str, prefix: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(at) = rest.find(prefix) {
+    let (before, after) = rest.split_at(at + prefix.len());
+    out.push_str(before);
+    let end = after
+      .find(|c: char| c == '&' || c == '"' || c == ',' || c == '}' || c.is_whitespace())
+      .unwrap_or(after.len());
+    if end > 0 && &after[..end] != REDACTED {
+      out.push_str(REDACTED);
+    } else {
+      out.push_str(&after[..end]);
+    }
+    rest = &after[end..];
+  }
+  out.push_str(rest);
+  out
+}
+
+fn parse_level(level: &str) -> Result<LogLevelFilter> {
+  Ok(LogLevelFilter::from_str(level).map_err(|_| {
+    Problem::ConfigInvalid(format!(
+      "log level {:?} - use off, error, warn, info, debug or trace",
+      level
+    ))
+  })?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Secrets are process-wide, and eviction would race with other tests, so
+  // the registry is exercised in one test.
+  #[test]
+  fn scrubs_registered_secrets() {
+    secret("first-secret-value");
+    assert_eq!(redact("got first-secret-value back"), "got [redacted] back");
+
+    secret("short");
+    assert_eq!(redact("a short line"), "a short line");
+
+    forget_secret("first-secret-value");
+    assert_eq!(redact("first-secret-value"), "first-secret-value");
+
+    for n in 0..MAX_SEEN + 1 {
+      secret(&format!("many-secrets-{:04}", n));
+    }
+    assert_eq!(redact("many-secrets-0000"), "many-secrets-0000");
+    assert_eq!(redact(&format!("many-secrets-{:04}", MAX_SEEN)), REDACTED);
+  }
+
+  #[test]
+  fn scrubs_named_values() {
+    assert_eq!(
+      redact("GET /oauth?code=abc123&state=xyz HTTP/1.1"),
+      "GET /oauth?code=[redacted]&state=[redacted] HTTP/1.1"
+    );
+    assert_eq!(
+      redact(r#"{"access_token":"abc","expires_in":3600}"#),
+      r#"{"access_token":"[redacted]","expires_in":3600}"#
+    );
+    assert_eq!(redact(r#"api_key: "abc""#), r#"api_key: "[redacted]""#);
+    assert_eq!(redact("Authorization: Bearer abc.def"), "Authorization: Bearer [redacted]");
+  }
+
+  #[test]
+  fn leaves_empty_values_alone() {
+    assert_eq!(redact("token=&next=1"), "token=&next=1");
+  }
+}
@@ -18,6 +18,7 @@ extern crate oauth2;
 extern crate rand;
 extern crate base64;
 extern crate toml;
+#[macro_use]
 extern crate serde;
 extern crate serde_json;
 extern crate zip;
@@ -33,6 +34,12 @@ extern crate fern;
 extern crate log;
 extern crate chrono;
 extern crate hyper_staticfile;
+extern crate unicode_width;
+extern crate term_size;
+#[macro_use]
+extern crate lazy_static;
+extern crate openssl;
+extern crate tokio_io;
 
 
 mod state;
@@ -41,14 +48,18 @@ mod destiny;
 mod errors;
 mod table;
 mod server;
+mod cli;
+mod metrics;
+mod logging;
 
 fn main() {
   use ::std::io::Write;
 
-  ::std::process::exit(match server::start_http() {
+  ::std::process::exit(match cli::run(::std::env::args().skip(1).collect()) {
     Ok(_) => 0,
     Err(ref e) => {
-      write!(&mut ::std::io::stderr(), "{}\n", e).expect("Error writing to stderr");
+      write!(&mut ::std::io::stderr(), "{}\n", logging::redact(&e.to_string()))
+        .expect("Error writing to stderr");
       1
     }
   });
@@ -0,0 +1,129 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Upper bounds, in seconds, of the Bungie API latency histogram.
+static LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// A session counts as active if it's made a request this recently.
+const SESSION_WINDOW_SECS: u64 = 15 * 60;
+
+#[derive(Default)]
+struct Metrics {
+  api_calls: BTreeMap<String, u64>,
+  api_latency_buckets: Vec<u64>,
+  api_latency_sum: f64,
+  api_latency_count: u64,
+  api_retries: u64,
+  manifest_cache_hits: u64,
+  manifest_downloads: u64,
+  definitions_found: u64,
+  definitions_missing: u64,
+  // Keyed by a hash of the access token, so the tokens themselves aren't kept.
+  sessions: HashMap<u64, Instant>,
+}
+
+lazy_static! {
+  static ref METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    api_latency_buckets: vec![0; LATENCY_BUCKETS.len()],
+    ..Metrics::default()
+  });
+}
+
+/// Records one Bungie API call, by response status or "error" if none came.
+pub fn api_call(status: &str, elapsed: Duration) {
+  let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+  let mut metrics = METRICS.lock().unwrap();
+  *metrics.api_calls.entry(status.to_owned()).or_insert(0) += 1;
+  for (count, bound) in metrics.api_latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+    if seconds <= *bound {
+      *count += 1;
+    }
+  }
+  metrics.api_latency_sum += seconds;
+  metrics.api_latency_count += 1;
+}
+
+pub fn api_retry() {
+  METRICS.lock().unwrap().api_retries += 1;
+}
+
+/// Records whether the manifest database was already on disk, or had to be
+/// downloaded.
+pub fn manifest_lookup(cached: bool) {
+  let mut metrics = METRICS.lock().unwrap();
+  if cached {
+    metrics.manifest_cache_hits += 1;
+  } else {
+    metrics.manifest_downloads += 1;
+  }
+}
+
+pub fn definition_lookup(found: bool) {
+  let mut metrics = METRICS.lock().unwrap();
+  if found {
+    metrics.definitions_found += 1;
+  } else {
+    metrics.definitions_missing += 1;
+  }
+}
+
+pub fn session_seen(token: &str) {
+  METRICS.lock().unwrap().sessions.insert(token_key(token), Instant::now());
+}
+
+pub fn session_ended(token: &str) {
+  METRICS.lock().unwrap().sessions.remove(&token_key(token));
+}
+
+/// Everything recorded so far, in the Prometheus text exposition format.
+pub fn render() -> String {
+  let mut metrics = METRICS.lock().unwrap();
+  let window = Duration::from_secs(SESSION_WINDOW_SECS);
+  metrics.sessions.retain(|_, seen| seen.elapsed() < window);
+
+  let mut out = String::new();
+  let _ = writeln!(out, "# HELP d2tools_api_requests_total Bungie API calls, by response status.");
+  let _ = writeln!(out, "# TYPE d2tools_api_requests_total counter");
+  for (status, count) in &metrics.api_calls {
+    let _ = writeln!(out, "d2tools_api_requests_total{{status=\"{}\"}} {}", status, count);
+  }
+
+  let _ = writeln!(out, "# HELP d2tools_api_request_seconds Bungie API call latency, including retries.");
+  let _ = writeln!(out, "# TYPE d2tools_api_request_seconds histogram");
+  for (count, bound) in metrics.api_latency_buckets.iter().zip(LATENCY_BUCKETS) {
+    let _ = writeln!(out, "d2tools_api_request_seconds_bucket{{le=\"{}\"}} {}", bound, count);
+  }
+  let _ = writeln!(out, "d2tools_api_request_seconds_bucket{{le=\"+Inf\"}} {}", metrics.api_latency_count);
+  let _ = writeln!(out, "d2tools_api_request_seconds_sum {}", metrics.api_latency_sum);
+  let _ = writeln!(out, "d2tools_api_request_seconds_count {}", metrics.api_latency_count);
+
+  counter(&mut out, "d2tools_api_retries_total", "Bungie API calls retried after a failure.", metrics.api_retries);
+  counter(&mut out, "d2tools_manifest_cache_hits_total", "Manifest databases found already downloaded.", metrics.manifest_cache_hits);
+  counter(&mut out, "d2tools_manifest_downloads_total", "Manifest databases downloaded.", metrics.manifest_downloads);
+
+  let _ = writeln!(out, "# HELP d2tools_definition_lookups_total Manifest definition lookups, by whether the definition was found.");
+  let _ = writeln!(out, "# TYPE d2tools_definition_lookups_total counter");
+  let _ = writeln!(out, "d2tools_definition_lookups_total{{result=\"found\"}} {}", metrics.definitions_found);
+  let _ = writeln!(out, "d2tools_definition_lookups_total{{result=\"missing\"}} {}", metrics.definitions_missing);
+
+  let _ = writeln!(out, "# HELP d2tools_active_sessions Signed-in sessions with a request in the last {} minutes.", SESSION_WINDOW_SECS / 60);
+  let _ = writeln!(out, "# TYPE d2tools_active_sessions gauge");
+  let _ = writeln!(out, "d2tools_active_sessions {}", metrics.sessions.len());
+  out
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+  let _ = writeln!(out, "# HELP {} {}", name, help);
+  let _ = writeln!(out, "# TYPE {} counter", name);
+  let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn token_key(token: &str) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  token.hash(&mut hasher);
+  hasher.finish()
+}
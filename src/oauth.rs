@@ -5,6 +5,7 @@ use base64;
 use url;
 
 use errors::*;
+use logging;
 use state::AppConfig;
 
 pub fn authorize_url(url: &str, cfg: &AppConfig) -> String {
@@ -32,22 +33,32 @@ fn oauth_config(url: &str, cfg: &AppConfig) -> Config {
 pub fn extract_token(cfg: &AppConfig, uri: &Uri) -> Result<Token> {
   let code = value_from_query(uri, "code")?;
   let state = value_from_query(uri, "state")?;
-
-  println!("Oauth code:\n{}\n", code);
-  println!("Echoed state:\n{}\n", state);
+  logging::secret(&code);
+  logging::secret(&state);
+  debug!("Received OAuth code and state");
 
   let config = oauth_config(cfg.oauth_url()?.as_str(), &cfg);
 
-  Ok(config.exchange_code(code)?)
+  // A code that won't exchange is stale or forged; either way, sign in again.
+  let token = config.exchange_code(code).map_err(|e| {
+    error!("Exchanging OAuth code: {}", e);
+    Problem::AuthRequired
+  })?;
+  logging::secret(&token.access_token);
+  if let Some(ref refresh) = token.refresh_token {
+    logging::secret(refresh);
+  }
+  Ok(token)
 }
 
 fn value_from_query(uri: &hyper::Uri, name: &str) -> Result<String> {
-  let query_string = uri.query().ok_or(format_err!("No query part"))?;
+  let missing = || Problem::BadRequest(format!("The sign-in callback has no {:?}", name));
+  let query_string = uri.query().ok_or_else(&missing)?;
   let pair = url::form_urlencoded::parse(query_string.as_bytes()).find(|pair| {
       let &(ref key, _) = pair;
       key == name
     })
-    .ok_or(format_err!("key not found"))?;
+    .ok_or_else(&missing)?;
   let (_, value) = pair;
   Ok(value.into_owned())
 }
@@ -0,0 +1,114 @@
+use destiny;
+use errors::*;
+use gotham::state::{FromState, State};
+use gotham::http::response::create_response;
+use gotham::middleware::session::SessionData;
+use hyper::server::Response;
+use hyper::StatusCode;
+use mime;
+use state::AppConfig;
+
+/// Shows which Bungie.net account this session is connected to.
+pub fn handler(mut state: State) -> (State, Response) {
+  debug!("Showing account");
+  let res = match account_body(&mut state) {
+    Ok(body) => create_response(
+      &state,
+      StatusCode::Ok,
+      Some((body.into_bytes(), mime::TEXT_PLAIN)),
+    ),
+    Err(e) => super::problem::response(&state, &e),
+  };
+  (state, res)
+}
+
+/// Asks before logging out: following a link, or a page fetching one,
+/// shouldn't be enough to end the session, so only POST does.
+pub fn logout_form_handler(state: State) -> (State, Response) {
+  let res = create_response(
+    &state,
+    StatusCode::Ok,
+    Some((LOGOUT_FORM.to_owned().into_bytes(), mime::TEXT_HTML_UTF_8)),
+  );
+  (state, res)
+}
+
+const LOGOUT_FORM: &str = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>Log out</title></head>
+<body>
+<form method=\"post\" action=\"/logout\">
+<p>Disconnect this browser from your Bungie.net account?</p>
+<button type=\"submit\">Log out</button>
+</form>
+<p><a href=\"/\">Back to the inventory</a></p>
+</body>
+</html>
+";
+
+/// Ends the session: the token and account are cleared, and the session
+/// itself is dropped from the store and its cookie expired.
+pub fn logout_handler(mut state: State) -> (State, Response) {
+  debug!("Logging out");
+  let mut session = SessionData::<super::D2Session>::take_from(&mut state);
+  session.clear();
+  let res = match session.discard(&mut state) {
+    Ok(()) => create_response(
+      &state,
+      StatusCode::Ok,
+      Some((
+        "Logged out. This server has forgotten the Bungie.net token, though Bungie.net\n\
+         doesn't revoke it. Visit / to connect a Bungie.net account again.\n"
+          .to_owned()
+          .into_bytes(),
+        mime::TEXT_PLAIN,
+      )),
+    ),
+    Err(e) => super::problem::response(&state, &format_err!("Discarding session: {:?}", e)),
+  };
+  (state, res)
+}
+
+/// The Bungie.net membership id of the session's account, which saved
+/// loadouts and snapshots are kept under.
+pub fn membership_id(state: &mut State) -> Result<String> {
+  Ok(identified(state)?.membership_id)
+}
+
+fn account_body(state: &mut State) -> Result<String> {
+  let account = identified(state)?;
+  Ok(format!(
+    "Connected to Bungie.net as {} ({})\nMembership id: {}\nDestiny memberships: {}\n\nLog out at /logout\n",
+    account.display_name,
+    account.unique_name,
+    account.membership_id,
+    account.memberships.join(", ")
+  ))
+}
+
+fn identified(state: &mut State) -> Result<destiny::Account> {
+  let api_key = state
+    .try_borrow::<AppConfig>()
+    .ok_or(format_err!("No app config in state?"))?
+    .api_key
+    .clone();
+  let (token, known) = {
+    let session = SessionData::<super::D2Session>::borrow_from(state);
+    (
+      session.token.clone().map(|t| t.access_token),
+      session.account.clone(),
+    )
+  };
+  let token = token.ok_or(Problem::AuthRequired)?;
+
+  // Sessions from before accounts were recorded, or whose lookup failed at
+  // sign-in, get it filled in now.
+  match known {
+    Some(account) => Ok(account),
+    None => {
+      let account = destiny::account(token, api_key)?;
+      SessionData::<super::D2Session>::borrow_mut_from(state).identify(account.clone());
+      Ok(account)
+    }
+  }
+}
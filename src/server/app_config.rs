@@ -2,7 +2,6 @@ use gotham::middleware::{Middleware, NewMiddleware};
 use gotham::state::State;
 use gotham::handler::HandlerFuture;
 use std::io;
-use std::env;
 use state::AppConfig;
 use futures::{future, Future};
 
@@ -23,15 +22,7 @@ impl Middleware for Ware {
     Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
     Self: Sized,
   {
-    let cfg = AppConfig {
-      canonical_url: env::var("CANONICAL_URL").unwrap_or_default(),
-      oauth_path: env::var("OAUTH_PATH").unwrap_or_default(),
-      api_key: env::var("API_KEY").unwrap_or_default(),
-      client_id: env::var("CLIENT_ID").unwrap_or_default(),
-      client_secret: env::var("CLIENT_SECRET").unwrap_or_default(),
-      access_token: "".to_owned(),
-      refresh_token: "".to_owned(),
-    };
+    let cfg = AppConfig::from_env();
 
     debug!("AppConfig: putting config in state");
     state.put(cfg);
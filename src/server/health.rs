@@ -0,0 +1,67 @@
+use destiny;
+use gotham::state::State;
+use gotham::http::response::create_response;
+use hyper::server::Response;
+use hyper::StatusCode;
+use mime;
+use metrics;
+use state::AppConfig;
+use table;
+
+/// Answers as long as the server is up at all.
+pub fn healthz_handler(state: State) -> (State, Response) {
+  let res = create_response(
+    &state,
+    StatusCode::Ok,
+    Some(("ok\n".to_owned().into_bytes(), mime::TEXT_PLAIN)),
+  );
+  (state, res)
+}
+
+/// Ready once the configuration is complete and the manifest is usable;
+/// otherwise 503, listing what's wrong.
+pub fn readyz_handler(state: State) -> (State, Response) {
+  let mut problems = match state.try_borrow::<AppConfig>() {
+    Some(cfg) => cfg.problems(),
+    None => vec!["No app config in state".to_owned()],
+  };
+  if let Err(e) = destiny::check_manifest() {
+    problems.push(format!("manifest: {}", e));
+  }
+
+  let (status, body) = if problems.is_empty() {
+    (StatusCode::Ok, "ready\n".to_owned())
+  } else {
+    warn!("Not ready: {}", problems.join("; "));
+    (StatusCode::ServiceUnavailable, format!("not ready\n{}\n", problems.join("\n")))
+  };
+  let res = create_response(&state, status, Some((body.into_bytes(), mime::TEXT_PLAIN)));
+  (state, res)
+}
+
+pub fn metrics_handler(state: State) -> (State, Response) {
+  let res = create_response(
+    &state,
+    StatusCode::Ok,
+    Some((metrics::render().into_bytes(), mime::TEXT_PLAIN_UTF_8)),
+  );
+  (state, res)
+}
+
+/// How the responses seen so far differ from the DTOs, when AUDIT_SCHEMA is
+/// on.
+pub fn schema_report_handler(state: State) -> (State, Response) {
+  let res = match destiny::schema_report() {
+    Some(report) => create_response(
+      &state,
+      StatusCode::Ok,
+      Some((report.render(table::Format::Text).into_bytes(), mime::TEXT_PLAIN_UTF_8)),
+    ),
+    None => create_response(
+      &state,
+      StatusCode::NotFound,
+      Some(("Schema audit is off; set AUDIT_SCHEMA=1 to turn it on.\n".to_owned().into_bytes(), mime::TEXT_PLAIN)),
+    ),
+  };
+  (state, res)
+}
@@ -1,34 +1,150 @@
+use std::thread;
 use destiny;
 use errors::*;
-use gotham::state::State;
+use futures::{Future, Sink};
+use gotham::state::{FromState, State};
 use gotham::http::response::create_response;
+use gotham::middleware::session::SessionData;
 use hyper::server::Response;
-use hyper::StatusCode;
-use mime;
+use hyper::header::ContentType;
+use hyper::{Body, Chunk, StatusCode, Uri};
+use mime::{self, Mime};
+use rand::{self, Rng};
 use state::AppConfig;
+use table;
 
 pub fn handler(gstate: State) -> (State, Response) {
   debug!("Assembling inventory");
-  let res = match body(&gstate) {
-    Ok(string) => create_response(
-      &gstate,
-      StatusCode::Ok,
-      Some((string.into_bytes(), mime::TEXT_PLAIN)),
-    ),
-    Err(e) => {
-      error!("{}", e);
-      create_response(
-        &gstate,
-        StatusCode::InternalServerError,
-        Some((format!("{:?}", e).into_bytes(), mime::TEXT_PLAIN)),
-      )
-    }
+  let res = match super::query_flag(Uri::borrow_from(&gstate), "stream") {
+    Ok(true) if !super::wants_json(&gstate) => match streamed_response(&gstate) {
+      Ok(res) => res,
+      Err(e) => respond(&gstate, Err(e)),
+    },
+    Ok(_) => respond(&gstate, body(&gstate)),
+    Err(e) => respond(&gstate, Err(e)),
   };
+  (gstate, res)
+}
+
+pub fn perks_handler(gstate: State) -> (State, Response) {
+  debug!("Assembling perks");
+  let res = respond(&gstate, perks_body(&gstate));
+  (gstate, res)
+}
+
+pub fn max_power_handler(gstate: State) -> (State, Response) {
+  debug!("Assembling max power loadouts");
+  let res = respond(&gstate, max_power_body(&gstate));
+  (gstate, res)
+}
+
+pub fn vendors_handler(gstate: State) -> (State, Response) {
+  debug!("Assembling vendor sales");
+  let res = respond(&gstate, vendors_body(&gstate));
+  (gstate, res)
+}
+
+pub fn progress_handler(gstate: State) -> (State, Response) {
+  debug!("Assembling character progress");
+  let res = respond(&gstate, progress_body(&gstate));
+  (gstate, res)
+}
+
+pub fn pursuits_handler(gstate: State) -> (State, Response) {
+  debug!("Assembling pursuits");
+  let res = respond(&gstate, pursuits_body(&gstate));
+  (gstate, res)
+}
+
+pub fn collections_handler(gstate: State) -> (State, Response) {
+  debug!("Assembling collections");
+  let res = respond(&gstate, collections_body(&gstate));
+  (gstate, res)
+}
+
+pub fn history_handler(mut gstate: State) -> (State, Response) {
+  debug!("Listing snapshots");
+  let body = super::account::membership_id(&mut gstate)
+    .and_then(|id| history_body(&gstate, &id));
+  let res = respond(&gstate, body);
+  (gstate, res)
+}
 
+pub fn diff_handler(mut gstate: State) -> (State, Response) {
+  debug!("Comparing snapshots");
+  let body = super::account::membership_id(&mut gstate)
+    .and_then(|id| diff_body(&gstate, &id));
+  let res = respond(&gstate, body);
   (gstate, res)
 }
 
-fn body(state: &State) -> Result<String> {
+pub fn loadouts_handler(mut gstate: State) -> (State, Response) {
+  debug!("Listing loadouts");
+  let body = super::account::membership_id(&mut gstate)
+    .and_then(|id| loadouts_body(&gstate, &id));
+  let res = respond(&gstate, body);
+  (gstate, res)
+}
+
+// A dry run: GET shows what POST /loadouts/apply would do, and hands out
+// the token that confirms it.
+pub fn loadout_plan_handler(mut gstate: State) -> (State, Response) {
+  debug!("Planning loadout");
+  let res = match super::account::membership_id(&mut gstate)
+    .and_then(|id| plan_loadout_response(&mut gstate, &id))
+  {
+    Ok(res) => res,
+    Err(e) => super::problem::response(&gstate, &e),
+  };
+  (gstate, res)
+}
+
+pub fn loadout_apply_handler(mut gstate: State) -> (State, Response) {
+  debug!("Applying loadout");
+  let body = confirmed_plan(&mut gstate).and_then(|plan| apply_loadout_body(&gstate, plan));
+  let res = respond(&gstate, body);
+  (gstate, res)
+}
+
+pub fn loadout_save_handler(mut gstate: State) -> (State, Response) {
+  debug!("Saving loadout");
+  // Saving over a name loses what was there, so a page elsewhere mustn't be
+  // able to do it by posting here.
+  let body = super::same_origin(&gstate)
+    .and_then(|()| super::account::membership_id(&mut gstate))
+    .and_then(|id| save_loadout_body(&gstate, &id));
+  let res = respond(&gstate, body);
+  (gstate, res)
+}
+
+fn respond(gstate: &State, body: Result<(String, Mime)>) -> Response {
+  match body {
+    Ok((string, mime)) => create_response(
+      gstate,
+      StatusCode::Ok,
+      Some((string.into_bytes(), mime)),
+    ),
+    Err(e) => super::problem::response(gstate, &e),
+  }
+}
+
+fn table_options(state: &State) -> Result<destiny::TableOptions> {
+  let cfg = state
+    .try_borrow::<AppConfig>()
+    .ok_or(format_err!("No app config in state?"))?;
+
+  let mut opts = destiny::TableOptions::default();
+  opts.wishlist_path = cfg.wishlist();
+  for (name, value) in super::query_pairs(Uri::borrow_from(state)) {
+    opts
+      .set(&name, &value)
+      .map_err(|e| Problem::BadRequest(e.to_string()))?;
+  }
+  Ok(opts)
+}
+
+// Returns the session's access token and the app's API key.
+fn credentials(state: &State) -> Result<(String, String)> {
   let cfg = state
     .try_borrow::<AppConfig>()
     .ok_or(format_err!("No app config in state?"))?;
@@ -38,10 +154,240 @@ fn body(state: &State) -> Result<String> {
   let token = session
     .clone()
     .token
-    .ok_or(format_err!("Not authenticated"))?
+    .ok_or(Problem::AuthRequired)?
     .access_token;
-  Ok(format!(
-    "{}",
-    destiny::api_exchange(token, cfg.api_key.clone())?
+  Ok((token, cfg.api_key.clone()))
+}
+
+fn body(state: &State) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let opts = table_options(state)?;
+
+  if super::wants_json(state) {
+    return Ok((
+      destiny::api_exchange_json(token, api_key, &opts)?,
+      mime::APPLICATION_JSON,
+    ));
+  }
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::api_exchange(token, api_key, &opts)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+// Sends the table in chunks as items are fetched, from a thread of its own
+// so the server isn't held up. Once rows are going out the status is fixed,
+// so a failure part way through is reported at the end of the body.
+fn streamed_response(state: &State) -> Result<Response> {
+  let (token, api_key) = credentials(state)?;
+  let opts = table_options(state)?;
+  let format = super::table_format(state)?;
+  let mime: Mime = format.mime_type().parse()?;
+  let (sender, body) = Body::pair();
+
+  thread::spawn(move || {
+    let mut sender = sender;
+    let result = destiny::stream_inventory(token, api_key, &opts, format, |text| {
+      sender = sender
+        .clone()
+        .send(Ok(Chunk::from(text)))
+        .wait()
+        .map_err(|_| format_err!("Client went away"))?;
+      Ok(())
+    });
+    if let Err(e) = result {
+      error!("{:?}", e);
+      let message = super::problem::message(&e);
+      let _ = sender.send(Ok(Chunk::from(format!("\nError: {}\n", message)))).wait();
+    }
+  });
+
+  Ok(
+    Response::new()
+      .with_status(StatusCode::Ok)
+      .with_header(ContentType(mime))
+      .with_body(body),
+  )
+}
+
+fn perks_body(state: &State) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::perks(token, api_key)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn max_power_body(state: &State) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::max_power(token, api_key)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn vendors_body(state: &State) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let vendor = super::query_param(Uri::borrow_from(state), "vendor");
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::vendors(token, api_key, vendor.as_ref().map(|v| v.as_str()))?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn progress_body(state: &State) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::progress(token, api_key)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn pursuits_body(state: &State) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let incomplete = super::query_flag(Uri::borrow_from(state), "incomplete")?;
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::pursuits(token, api_key, incomplete)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn collections_body(state: &State) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let uri = Uri::borrow_from(state);
+  let filter = destiny::CollectionFilter {
+    source: super::query_param(uri, "source"),
+    season: super::query_param(uri, "season"),
+    missing_only: super::query_flag(uri, "missing")?,
+  };
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::collections(token, api_key, &filter)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn loadout_name(state: &State) -> Result<String> {
+  Ok(super::query_param(Uri::borrow_from(state), "name")
+    .ok_or_else(|| Problem::BadRequest("Which loadout? Pass ?name=".to_owned()))?)
+}
+
+fn loadouts_body(state: &State, membership_id: &str) -> Result<(String, Mime)> {
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::loadouts(membership_id)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+// Browsers get a page with a button to confirm the plan; anything else gets
+// the table, and the token to confirm it with in a header.
+fn plan_loadout_response(state: &mut State, membership_id: &str) -> Result<Response> {
+  let (token, api_key) = credentials(state)?;
+  let name = loadout_name(state)?;
+  let format = super::table_format(state)?;
+  let plan = destiny::plan_loadout(token, api_key, membership_id, &name)?;
+  let table = plan.table().render(format);
+  let confirm = rand::thread_rng()
+    .gen_iter::<u8>()
+    .take(16)
+    .map(|byte| format!("{:02x}", byte))
+    .collect::<String>();
+  SessionData::<super::D2Session>::borrow_mut_from(state).loadout_plan = Some(super::PendingPlan {
+    confirm: confirm.clone(),
+    plan,
+  });
+
+  let browser = format == table::Format::Text
+    && super::accepted_mime_types(state)
+      .first()
+      .map_or(false, |mime| mime == "text/html");
+  if browser {
+    let page = format!(
+      "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Loadout {name}</title></head>\n<body>\n<h1>Restoring {name}</h1>\n<pre>{table}</pre>\n<form method=\"post\" action=\"/loadouts/apply?confirm={confirm}\">\n<button type=\"submit\">Make these moves</button>\n</form>\n<p><a href=\"/loadouts\">Back to the loadouts</a></p>\n</body>\n</html>\n",
+      name = super::problem::escape(&name),
+      table = super::problem::escape(&table),
+      confirm = confirm
+    );
+    return Ok(create_response(
+      state,
+      StatusCode::Ok,
+      Some((page.into_bytes(), mime::TEXT_HTML_UTF_8)),
+    ));
+  }
+  let mut res = create_response(
+    state,
+    StatusCode::Ok,
+    Some((table.into_bytes(), format.mime_type().parse()?)),
+  );
+  res.headers_mut().set_raw("X-Loadout-Confirm", confirm);
+  Ok(res)
+}
+
+// The plan this session was last shown, if the request carries the token it
+// went out with. Each plan is applied at most once.
+fn confirmed_plan(state: &mut State) -> Result<destiny::LoadoutPlan> {
+  let no_plan = || {
+    Problem::BadRequest(
+      "No plan to confirm - plan the loadout at /loadouts/plan?name= and confirm it from there"
+        .to_owned(),
+    )
+  };
+  let confirm = super::query_param(Uri::borrow_from(state), "confirm");
+  let session = SessionData::<super::D2Session>::borrow_mut_from(state);
+  let confirmed = match (&session.loadout_plan, &confirm) {
+    (&Some(ref pending), &Some(ref confirm)) => pending.confirm == *confirm,
+    _ => false,
+  };
+  if !confirmed {
+    return Err(no_plan().into());
+  }
+  Ok(session.loadout_plan.take().ok_or_else(no_plan)?.plan)
+}
+
+fn apply_loadout_body(state: &State, plan: destiny::LoadoutPlan) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::apply_loadout(token, api_key, plan)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn save_loadout_body(state: &State, membership_id: &str) -> Result<(String, Mime)> {
+  let (token, api_key) = credentials(state)?;
+  let name = loadout_name(state)?;
+  let count = destiny::save_loadout(token, api_key, membership_id, &name)?;
+  Ok((
+    format!("Saved {} equipped items as {:?}\n", count, name),
+    mime::TEXT_PLAIN,
+  ))
+}
+
+fn history_body(state: &State, membership_id: &str) -> Result<(String, Mime)> {
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::snapshots(membership_id)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
+
+fn diff_body(state: &State, membership_id: &str) -> Result<(String, Mime)> {
+  let since = match super::query_param(Uri::borrow_from(state), "since") {
+    Some(id) => Some(id
+      .parse()
+      .map_err(|_| Problem::BadRequest(format!("since {:?} isn't a snapshot number", id)))?),
+    None => None,
+  };
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::snapshot_diff(membership_id, since)?.render(format),
+    format.mime_type().parse()?,
   ))
 }
@@ -1,55 +1,212 @@
 use errors::*;
 
-use fern;
+use std::net::{SocketAddr, TcpListener as StdTcpListener, TcpStream as StdTcpStream};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::thread;
+use std::time::Duration;
+
+use futures::sync::oneshot;
 use log::LogLevelFilter;
-use chrono::prelude::*;
 use oauth2::Token;
+use gotham::state::{FromState, State};
+use hyper::{Headers, Uri};
+use hyper::header::Accept;
+use url;
+
+use destiny;
+use logging;
+use metrics;
+use state::AppConfig;
+use table;
 
 mod router;
 mod app_config;
 mod require_authn;
 mod oauth_receiver;
 mod inventory;
+mod search;
+mod account;
+mod problem;
+mod tls;
+mod health;
 
 #[derive(Default, Serialize, Deserialize, StateData, Clone)]
 struct D2Session {
   #[serde(default)]
   pub token: Option<Token>,
+  #[serde(default)]
+  pub account: Option<destiny::Account>,
+  #[serde(default)]
+  pub loadout_plan: Option<PendingPlan>,
+}
+
+/// A loadout plan shown to the user, waiting for them to confirm it. The
+/// token goes out with the plan and has to come back with the POST, so
+/// another site can't apply a loadout by posting to us.
+#[derive(Serialize, Deserialize, Clone)]
+struct PendingPlan {
+  confirm: String,
+  plan: destiny::LoadoutPlan,
 }
 
 impl D2Session {
   fn acquire_token(&mut self, t: Token) {
     self.token = Some(t);
   }
+
+  fn identify(&mut self, account: destiny::Account) {
+    self.account = Some(account);
+  }
+
+  /// Forgets the token, account and any pending plan, and anything cached
+  /// or kept for log scrubbing under the token.
+  fn clear(&mut self) {
+    if let Some(ref token) = self.token {
+      destiny::forget_profile(&token.access_token);
+      metrics::session_ended(&token.access_token);
+      logging::forget_secret(&token.access_token);
+      if let Some(ref refresh) = token.refresh_token {
+        logging::forget_secret(refresh);
+      }
+    }
+    self.token = None;
+    self.account = None;
+    self.loadout_plan = None;
+  }
+}
+
+fn query_param(uri: &Uri, name: &str) -> Option<String> {
+  query_pairs(uri)
+    .into_iter()
+    .find(|&(ref key, _)| key == name)
+    .map(|(_, value)| value)
+}
+
+/// Whether the on/off option `name` is set in the query string.
+fn query_flag(uri: &Uri, name: &str) -> Result<bool> {
+  match query_param(uri, name) {
+    Some(value) => destiny::parse_flag(name, &value),
+    None => Ok(false),
+  }
+}
+
+fn query_pairs(uri: &Uri) -> Vec<(String, String)> {
+  uri.query().map_or(Vec::new(), |query| {
+    url::form_urlencoded::parse(query.as_bytes())
+      .into_owned()
+      .collect()
+  })
+}
+
+fn accepted_mime_types(state: &State) -> Vec<String> {
+  Headers::borrow_from(state).get::<Accept>().map_or(Vec::new(), |accept| {
+    accept
+      .iter()
+      .map(|quality| format!("{}/{}", quality.item.type_(), quality.item.subtype()))
+      .collect()
+  })
+}
+
+/// Refuses a request a browser made on another site's behalf. Browsers say
+/// where a POST came from, by Origin or else Referer, and that has to be
+/// this server; clients that say neither aren't browsers, so they pass.
+fn same_origin(state: &State) -> Result<()> {
+  let headers = Headers::borrow_from(state);
+  let from = match raw_header(headers, "Origin").or_else(|| raw_header(headers, "Referer")) {
+    Some(from) => from,
+    None => return Ok(()),
+  };
+  match raw_header(headers, "Host") {
+    Some(host) if authority(from) == Some(host) => Ok(()),
+    _ => Err(Problem::CrossSite.into()),
+  }
+}
+
+fn raw_header<'h>(headers: &'h Headers, name: &str) -> Option<&'h str> {
+  headers
+    .get_raw(name)
+    .and_then(|raw| raw.one())
+    .and_then(|value| str::from_utf8(value).ok())
+}
+
+// The host and port of an Origin or Referer URL. An opaque origin, sent as
+// "null", has none.
+fn authority(url: &str) -> Option<&str> {
+  url.splitn(2, "://").nth(1).and_then(|rest| rest.split('/').next())
+}
+
+/// Whole JSON, as opposed to the JSON Lines rendering of table rows, asked
+/// for with `?format=json` or an Accept header preferring it.
+fn wants_json(state: &State) -> bool {
+  match query_param(Uri::borrow_from(state), "format") {
+    Some(format) => format == "json",
+    None => accepted_mime_types(state)
+      .first()
+      .map_or(false, |mime| mime == "application/json"),
+  }
+}
+
+/// The table format asked for with `?format=`, or failing that, the first
+/// one we can produce from the Accept header.
+fn table_format(state: &State) -> Result<table::Format> {
+  if let Some(name) = query_param(Uri::borrow_from(state), "format") {
+    return Ok(table::Format::from_name(&name)
+      .ok_or_else(|| Problem::BadRequest(format!("Unknown format {:?}", name)))?);
+  }
+  Ok(
+    accepted_mime_types(state)
+      .iter()
+      .filter_map(|mime| table::Format::from_mime_type(mime))
+      .next()
+      .unwrap_or(table::Format::Text),
+  )
 }
 
 pub fn start_http() -> Result<()> {
-  let addr = "127.0.0.1:8181";
-
-  configure_logging();
-
-  Ok(::gotham::start(addr, router::new()))
-}
-
-fn configure_logging() {
-  fern::Dispatch::new()
-    .level(LogLevelFilter::Debug)
-    .level_for("tokio_core::reactor", LogLevelFilter::Error)
-    .level_for("tokio_core", LogLevelFilter::Error)
-    .level_for(
-      "tokio_proto::streaming::pipeline::advanced",
-      LogLevelFilter::Error,
-    )
-    .chain(::std::io::stdout())
-    .format(|out, message, record| {
-      out.finish(format_args!(
-        "[{}] {}[{}] {}",
-        Utc::now().format("%Y-%m-%d %H:%M:%S%.9f"),
-        record.target(),
-        record.level(),
-        message
-      ))
-    })
-    .apply()
-    .unwrap();
+  let cfg = AppConfig::from_env();
+
+  logging::configure(&cfg, LogLevelFilter::Info, false)?;
+  cfg.apply_debug_options();
+  destiny::cache_profiles(cfg.profile_ttl()?);
+
+  let (cert, key) = match cfg.tls()? {
+    Some(paths) => paths,
+    None => return Ok(::gotham::start(cfg.listen_addr, router::new())),
+  };
+
+  // gotham listens on a loopback port of its own and we terminate TLS in
+  // front of it. The port can be taken between picking it and gotham
+  // binding it - see the tls module. If it is, gotham's thread ends, and
+  // the TLS side stops with it.
+  let acceptor = tls::acceptor(&cert, &key)?;
+  let public: SocketAddr = cfg
+    .listen_addr
+    .parse()
+    .map_err(|e| Problem::ConfigInvalid(format!("LISTEN_ADDR {:?}: {}", cfg.listen_addr, e)))?;
+  let backend = StdTcpListener::bind("127.0.0.1:0")?.local_addr()?;
+  // Dropped unsent if gotham panics, which completes the receiver all the same.
+  let (exited, backend_exit) = oneshot::channel::<()>();
+  thread::spawn(move || {
+    ::gotham::start(backend, router::new());
+    let _ = exited.send(());
+  });
+  wait_for(backend)?;
+  tls::serve(public, backend, acceptor, backend_exit)
+}
+
+// Waits for the backend to take connections, so the first requests relayed
+// aren't refused.
+fn wait_for(backend: SocketAddr) -> Result<()> {
+  for _ in 0..100 {
+    if StdTcpStream::connect(backend).is_ok() {
+      return Ok(());
+    }
+    thread::sleep(Duration::from_millis(50));
+  }
+  bail!("The HTTP server didn't start listening on {}", backend)
+}
+
+pub fn generate_dev_cert(dir: &Path) -> Result<(PathBuf, PathBuf)> {
+  tls::generate_dev_cert(dir)
 }
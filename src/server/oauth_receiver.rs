@@ -4,6 +4,7 @@ use hyper::StatusCode;
 use hyper::Uri;
 use hyper::header::Location;
 use state::AppConfig;
+use destiny;
 use oauth;
 use errors::*;
 use gotham::middleware::session::SessionData;
@@ -22,26 +23,28 @@ pub fn handler(mut state: State) -> (State, Response) {
         .with_status(StatusCode::Found)
         .with_header(Location::new("/")),
     ),
-    Err(e) => (
-      state,
-      Response::new()
-        .with_status(StatusCode::NotFound)
-        .with_body(format!(
-          "Something went wrong getting the code and state: {}\n",
-          e
-        )),
-    ),
+    Err(e) => {
+      let res = super::problem::response(&state, &e);
+      (state, res)
+    }
   }
 }
 
 fn get_oauth_stuff(state: &mut State, uri: &Uri) -> Result<()> {
-  let token = {
+  let (token, api_key) = {
     let cfg = state
       .try_borrow::<AppConfig>()
       .ok_or(format_err!("No app config in state?"))?;
-    oauth::extract_token(cfg, uri)?
+    (oauth::extract_token(cfg, uri)?, cfg.api_key.clone())
   };
+  // Without the account we can still serve pages, so it's looked up again
+  // from the account page rather than failing the sign-in.
+  let account = destiny::account(token.access_token.clone(), api_key);
   let session = SessionData::<super::D2Session>::borrow_mut_from(state);
   session.acquire_token(token);
+  match account {
+    Ok(account) => session.identify(account),
+    Err(e) => error!("Couldn't look up the Bungie account: {}", e),
+  }
   Ok(())
 }
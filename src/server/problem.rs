@@ -0,0 +1,103 @@
+use std::time::Duration;
+use errors::*;
+use gotham::state::State;
+use gotham::http::response::create_response;
+use hyper::server::Response;
+use hyper::StatusCode;
+use hyper::header::RetryAfter;
+use mime;
+use serde_json;
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+  status: u16,
+  error: &'a str,
+  message: String,
+}
+
+/// Turns an error into a response: a status code that fits what went wrong,
+/// and an HTML page or JSON body saying so in terms a user can act on. The
+/// full error, with its causes, only goes to the log.
+pub fn response(state: &State, err: &Error) -> Response {
+  let (status, kind, message) = describe(err);
+  if status == StatusCode::InternalServerError {
+    error!("{:?}", err);
+  } else {
+    warn!("{}: {}", kind, err);
+  }
+
+  let body = if super::wants_json(state) {
+    let body = ErrorBody {
+      status: status.as_u16(),
+      error: kind,
+      message: message,
+    };
+    (
+      serde_json::to_string(&body).unwrap_or_default().into_bytes(),
+      mime::APPLICATION_JSON,
+    )
+  } else {
+    (html_page(status, &message).into_bytes(), mime::TEXT_HTML_UTF_8)
+  };
+
+  let res = create_response(state, status, Some(body));
+  if kind == "throttled" {
+    res.with_header(RetryAfter::Delay(Duration::from_secs(60)))
+  } else {
+    res
+  }
+}
+
+/// What to tell the client about `err`, once a response is already under
+/// way and only the body is left to say it in.
+pub fn message(err: &Error) -> String {
+  describe(err).2
+}
+
+fn describe(err: &Error) -> (StatusCode, &'static str, String) {
+  let problem = match Problem::find(err) {
+    Some(problem) => problem,
+    None => {
+      return (
+        StatusCode::InternalServerError,
+        "internal",
+        "Something went wrong on our side - the details are in the server log.".to_owned(),
+      )
+    }
+  };
+  let (status, kind) = match *problem {
+    Problem::AuthRequired => (StatusCode::Unauthorized, "auth_required"),
+    Problem::Throttled => (StatusCode::TooManyRequests, "throttled"),
+    Problem::BungieDown(_) => (StatusCode::ServiceUnavailable, "bungie_down"),
+    Problem::ManifestMissing => (StatusCode::ServiceUnavailable, "manifest_missing"),
+    // Which setting is wrong is for the operator, not the visitor.
+    Problem::ConfigInvalid(_) => {
+      return (
+        StatusCode::InternalServerError,
+        "config_invalid",
+        "The server is misconfigured - the details are in the server log.".to_owned(),
+      )
+    }
+    Problem::BadRequest(_) => (StatusCode::BadRequest, "bad_request"),
+    Problem::CrossSite => (StatusCode::Forbidden, "cross_site"),
+  };
+  (status, kind, problem.to_string())
+}
+
+fn html_page(status: StatusCode, message: &str) -> String {
+  let title = escape(&status.to_string());
+  format!(
+    "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>\n<h1>{}</h1>\n<p>{}</p>\n<p><a href=\"/\">Back to the inventory</a></p>\n</body>\n</html>\n",
+    title,
+    title,
+    escape(message)
+  )
+}
+
+pub fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
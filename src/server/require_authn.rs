@@ -7,6 +7,7 @@ use std::io;
 use state::AppConfig;
 use oauth;
 use errors::*;
+use metrics;
 use gotham::handler::IntoHandlerFuture;
 use gotham::middleware::session::SessionData;
 
@@ -31,7 +32,12 @@ impl Middleware for Ware {
       debug!("Require Authn: Getting session from state");
       let session: &SessionData<super::D2Session> = state.borrow();
       match session.token {
-        Some(_) => None,
+        Some(ref token) => {
+          metrics::session_seen(&token.access_token);
+          None
+        }
+        // Browsers are sent to sign in; API clients just hear that they must.
+        None if super::wants_json(&state) => Some(Err(Problem::AuthRequired.into())),
         None => Some(redirect_response(state.borrow())),
       }
     };
@@ -40,11 +46,7 @@ impl Middleware for Ware {
       Some(result) => match result {
         Ok(r) => (state, r).into_handler_future(),
         Err(e) => {
-          error!("Require Authn: {}", e);
-          let res = Response::new()
-            .with_status(StatusCode::InternalServerError)
-            .with_body(format!("Require Authn: {}", e))
-            .with_header(ContentType::plaintext());
+          let res = super::problem::response(&state, &e);
           (state, res).into_handler_future()
         }
       },
@@ -23,8 +23,28 @@ pub fn new() -> Router {
 
   build_router(normal_pipeline, ps, |route| {
     route.get_or_head("/").to(super::inventory::handler);
+    route.get_or_head("/perks").to(super::inventory::perks_handler);
+    route.get_or_head("/max-power").to(super::inventory::max_power_handler);
+    route.get_or_head("/vendors").to(super::inventory::vendors_handler);
+    route.get_or_head("/progress").to(super::inventory::progress_handler);
+    route.get_or_head("/pursuits").to(super::inventory::pursuits_handler);
+    route.get_or_head("/collections").to(super::inventory::collections_handler);
+    route.get_or_head("/account").to(super::account::handler);
+    route.get_or_head("/history").to(super::inventory::history_handler);
+    route.get_or_head("/diff").to(super::inventory::diff_handler);
+    route.get_or_head("/loadouts").to(super::inventory::loadouts_handler);
+    route.get_or_head("/loadouts/plan").to(super::inventory::loadout_plan_handler);
+    route.post("/loadouts/apply").to(super::inventory::loadout_apply_handler);
+    route.post("/loadouts/save").to(super::inventory::loadout_save_handler);
+    route.get_or_head("/schema-report").to(super::health::schema_report_handler);
     route.with_pipeline_chain(bare_pipeline, |auth| {
       auth.get_or_head("/oauth").to(super::oauth_receiver::handler);
+      auth.get_or_head("/search").to(super::search::handler);
+      auth.get_or_head("/logout").to(super::account::logout_form_handler);
+      auth.post("/logout").to(super::account::logout_handler);
+      auth.get_or_head("/healthz").to(super::health::healthz_handler);
+      auth.get_or_head("/readyz").to(super::health::readyz_handler);
+      auth.get_or_head("/metrics").to(super::health::metrics_handler);
     });
   })
 }
@@ -0,0 +1,31 @@
+use destiny;
+use errors::*;
+use gotham::state::{FromState, State};
+use gotham::http::response::create_response;
+use hyper::server::Response;
+use hyper::{StatusCode, Uri};
+use mime::Mime;
+
+pub fn handler(gstate: State) -> (State, Response) {
+  debug!("Searching manifest");
+  let res = match body(&gstate) {
+    Ok((string, mime)) => create_response(
+      &gstate,
+      StatusCode::Ok,
+      Some((string.into_bytes(), mime)),
+    ),
+    Err(e) => super::problem::response(&gstate, &e),
+  };
+
+  (gstate, res)
+}
+
+fn body(state: &State) -> Result<(String, Mime)> {
+  let term = super::query_param(Uri::borrow_from(state), "q")
+    .ok_or_else(|| Problem::BadRequest("No search term - use ?q=<name or hash>".to_owned()))?;
+  let format = super::table_format(state)?;
+  Ok((
+    destiny::search_manifest(&term)?.render(format),
+    format.mime_type().parse()?,
+  ))
+}
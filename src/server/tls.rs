@@ -0,0 +1,196 @@
+//! HTTPS in front of gotham, which only speaks plain HTTP.
+//!
+//! Decrypted connections are relayed to gotham on a loopback port of its
+//! own. gotham 0.2 binds the address it's given itself and can't take a
+//! listener, so that port is found by binding 127.0.0.1:0 and closing it
+//! again before gotham rebinds it. In between, another process on the host
+//! can take the port. gotham then fails to bind and the server stops, but
+//! requests relayed before it does would reach that process decrypted, so
+//! on a machine shared with untrusted users put a TLS proxy in front of
+//! plain HTTP instead.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use futures::{Future, Poll, Stream};
+use native_tls::{Pkcs12, TlsAcceptor};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::pkcs12;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::{X509, X509NameBuilder};
+use openssl::x509::extension::SubjectAlternativeName;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::Core;
+use tokio_io::{io, AsyncRead, AsyncWrite};
+use tokio_tls::TlsAcceptorExt;
+use failure::ResultExt;
+
+use errors::*;
+
+// Only used to carry the key from openssl to native-tls in memory.
+const PKCS12_PASSWORD: &str = "d2tools";
+
+/// Builds a TLS acceptor from PEM certificate and key files. native-tls only
+/// takes PKCS#12 identities, so the pair is bundled into one first.
+pub fn acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
+  let cert = X509::from_pem(&read(cert_path)?)
+    .map_err(|e| Problem::ConfigInvalid(format!("TLS_CERT_PATH {:?}: {}", cert_path, e)))?;
+  let key = PKey::private_key_from_pem(&read(key_path)?)
+    .map_err(|e| Problem::ConfigInvalid(format!("TLS_KEY_PATH {:?}: {}", key_path, e)))?;
+  let bundle = pkcs12::Pkcs12::builder().build(PKCS12_PASSWORD, "d2tools", &key, &cert)?;
+  let identity = Pkcs12::from_der(&bundle.to_der()?, PKCS12_PASSWORD)?;
+  Ok(TlsAcceptor::builder(identity)?.build()?)
+}
+
+/// Accepts TLS connections on `public` and relays each one, decrypted, to
+/// the plain HTTP server listening on `backend`, until `backend_exit`
+/// completes: relaying to a port our server isn't on would hand requests to
+/// whoever is.
+pub fn serve<F: Future>(
+  public: SocketAddr,
+  backend: SocketAddr,
+  acceptor: TlsAcceptor,
+  backend_exit: F,
+) -> Result<()> {
+  let mut core = Core::new()?;
+  let handle = core.handle();
+  let listener = TcpListener::bind(&public, &handle)?;
+  info!("Serving HTTPS on {}", public);
+
+  let server = listener.incoming().for_each(|(socket, peer)| {
+    let connect_handle = handle.clone();
+    let relay = acceptor
+      .accept_async(socket)
+      .map_err(move |e| format_err!("TLS handshake with {}: {}", peer, e))
+      .and_then(move |tls| {
+        TcpStream::connect(&backend, &connect_handle)
+          .map(|plain| (tls, plain))
+          .map_err(Error::from)
+      })
+      .and_then(|(tls, plain)| {
+        let (tls_read, tls_write) = tls.split();
+        let plain = Backend(Rc::new(plain));
+        // Each direction runs until its sender is done and then passes the
+        // half-close on, so a client that has finished sending still gets
+        // the whole response.
+        let request = io::copy(tls_read, plain.clone())
+          .and_then(|(_, _, plain_write)| io::shutdown(plain_write));
+        let response = io::copy(plain, tls_write)
+          .and_then(|(_, _, tls_write)| io::shutdown(tls_write));
+        request.join(response).map_err(Error::from)
+      })
+      .map(|_| ())
+      .map_err(|e| debug!("Relay: {}", e));
+    handle.spawn(relay);
+    Ok(())
+  });
+
+  let stopped = backend_exit.then(move |_| -> Result<()> {
+    bail!("The HTTP server on {} stopped - was the port taken?", backend)
+  });
+  match core.run(server.map_err(Error::from).select(stopped)) {
+    Ok(_) => Ok(()),
+    Err((e, _)) => Err(e),
+  }
+}
+
+// The relay's connection to the backend. Both directions share the socket,
+// and shutting down the writing side closes only that half, so the backend
+// sees the end of the request and can still send the response.
+#[derive(Clone)]
+struct Backend(Rc<TcpStream>);
+
+impl Read for Backend {
+  fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+    (&*self.0).read(buf)
+  }
+}
+
+impl Write for Backend {
+  fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+    (&*self.0).write(buf)
+  }
+
+  fn flush(&mut self) -> ::std::io::Result<()> {
+    Ok(())
+  }
+}
+
+impl AsyncRead for Backend {}
+
+impl AsyncWrite for Backend {
+  fn shutdown(&mut self) -> Poll<(), ::std::io::Error> {
+    self.0.shutdown(Shutdown::Write)?;
+    Ok(().into())
+  }
+}
+
+/// Writes a self-signed certificate for localhost, and its key, as
+/// cert.pem and key.pem in `dir`. Browsers will warn about it, but Bungie
+/// only needs the redirect URL to be https.
+pub fn generate_dev_cert(dir: &Path) -> Result<(PathBuf, PathBuf)> {
+  let cert_path = dir.join("cert.pem");
+  let key_path = dir.join("key.pem");
+  for path in &[&cert_path, &key_path] {
+    if path.exists() {
+      bail!("{:?} already exists - not overwriting it", path)
+    }
+  }
+
+  let rsa = Rsa::generate(2048)?;
+  let key_pem = rsa.private_key_to_pem()?;
+  let key = PKey::from_rsa(rsa)?;
+
+  let mut name = X509NameBuilder::new()?;
+  name.append_entry_by_text("CN", "localhost")?;
+  let name = name.build();
+
+  let mut serial = BigNum::new()?;
+  serial.rand(64, MsbOption::MAYBE_ZERO, false)?;
+
+  let mut builder = X509::builder()?;
+  builder.set_version(2)?;
+  builder.set_serial_number(&serial.to_asn1_integer()?)?;
+  builder.set_subject_name(&name)?;
+  builder.set_issuer_name(&name)?;
+  builder.set_pubkey(&key)?;
+  builder.set_not_before(&Asn1Time::days_from_now(0)?)?;
+  builder.set_not_after(&Asn1Time::days_from_now(365)?)?;
+  let names = SubjectAlternativeName::new()
+    .dns("localhost")
+    .ip("127.0.0.1")
+    .build(&builder.x509v3_context(None, None))?;
+  builder.append_extension(names)?;
+  builder.sign(&key, MessageDigest::sha256())?;
+  let cert = builder.build();
+
+  fs::create_dir_all(dir)?;
+  write_new(&cert_path, &cert.to_pem()?, 0o644)?;
+  // Only we should be able to read the key, from the moment it exists.
+  write_new(&key_path, &key_pem, 0o600)?;
+  Ok((cert_path, key_path))
+}
+
+fn read(path: &str) -> Result<Vec<u8>> {
+  let mut bytes = Vec::new();
+  fs::File::open(path)
+    .with_context(|_| format!("opening {:?}", path))?
+    .read_to_end(&mut bytes)?;
+  Ok(bytes)
+}
+
+fn write_new(path: &Path, bytes: &[u8], mode: u32) -> Result<()> {
+  let mut file = OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .mode(mode)
+    .open(path)
+    .with_context(|_| format!("creating {:?}", path))?;
+  Ok(file.write_all(bytes)?)
+}
@@ -1,8 +1,13 @@
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
 use url::Url;
 
+use destiny;
 use errors::*;
 
-#[derive(Serialize, Deserialize, Debug, StateData)]
+#[derive(Serialize, Deserialize, StateData)]
 pub struct AppConfig {
   pub canonical_url: String,
   pub oauth_path: String,
@@ -14,11 +19,185 @@ pub struct AppConfig {
   pub access_token: String,
   #[serde(default)]
   pub refresh_token: String,
+
+  #[serde(default)]
+  pub wishlist_path: String,
+
+  /// Seconds the server keeps each user's profile before fetching it again.
+  /// See `profile_ttl()`.
+  #[serde(default)]
+  pub profile_ttl: String,
+
+  #[serde(default)]
+  pub listen_addr: String,
+  #[serde(default)]
+  pub tls_cert_path: String,
+  #[serde(default)]
+  pub tls_key_path: String,
+
+  #[serde(default)]
+  pub log_level: String,
+  #[serde(default)]
+  pub log_targets: String,
+  #[serde(default)]
+  pub log_file: String,
+  #[serde(default)]
+  pub log_format: String,
+
+  /// API responses are only dumped if this is set.
+  #[serde(default)]
+  pub debug_dump_dir: String,
+  #[serde(default)]
+  pub debug_dump_max_files: usize,
+  #[serde(default)]
+  pub debug_dump_max_bytes: u64,
+  /// Whether to report how responses differ from the DTOs.
+  #[serde(default)]
+  pub audit_schema: bool,
 }
 
+// Spelled out so the secrets never end up in a log line.
+impl fmt::Debug for AppConfig {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let masked = |value: &str| if value.is_empty() { "" } else { "***" };
+    f.debug_struct("AppConfig")
+      .field("canonical_url", &self.canonical_url)
+      .field("oauth_path", &self.oauth_path)
+      .field("api_key", &masked(&self.api_key))
+      .field("client_id", &self.client_id)
+      .field("client_secret", &masked(&self.client_secret))
+      .field("access_token", &masked(&self.access_token))
+      .field("refresh_token", &masked(&self.refresh_token))
+      .field("wishlist_path", &self.wishlist_path)
+      .field("profile_ttl", &self.profile_ttl)
+      .field("listen_addr", &self.listen_addr)
+      .field("tls_cert_path", &self.tls_cert_path)
+      .field("tls_key_path", &self.tls_key_path)
+      .field("log_level", &self.log_level)
+      .field("log_targets", &self.log_targets)
+      .field("log_file", &self.log_file)
+      .field("log_format", &self.log_format)
+      .field("debug_dump_dir", &self.debug_dump_dir)
+      .field("debug_dump_max_files", &self.debug_dump_max_files)
+      .field("debug_dump_max_bytes", &self.debug_dump_max_bytes)
+      .field("audit_schema", &self.audit_schema)
+      .finish()
+  }
+}
+
+const DEFAULT_PROFILE_TTL: u64 = 120;
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:8181";
+const DEFAULT_DUMP_MAX_FILES: usize = 200;
+const DEFAULT_DUMP_MAX_BYTES: u64 = 50 * 1024 * 1024;
+
 impl AppConfig {
+  pub fn from_env() -> AppConfig {
+    AppConfig {
+      canonical_url: env::var("CANONICAL_URL").unwrap_or_default(),
+      oauth_path: env::var("OAUTH_PATH").unwrap_or_default(),
+      api_key: env::var("API_KEY").unwrap_or_default(),
+      client_id: env::var("CLIENT_ID").unwrap_or_default(),
+      client_secret: env::var("CLIENT_SECRET").unwrap_or_default(),
+      access_token: env::var("ACCESS_TOKEN").unwrap_or_default(),
+      refresh_token: env::var("REFRESH_TOKEN").unwrap_or_default(),
+      wishlist_path: env::var("WISHLIST_PATH").unwrap_or_default(),
+      profile_ttl: env::var("PROFILE_TTL").unwrap_or_default(),
+      listen_addr: env::var("LISTEN_ADDR").unwrap_or(DEFAULT_LISTEN_ADDR.to_owned()),
+      tls_cert_path: env::var("TLS_CERT_PATH").unwrap_or_default(),
+      tls_key_path: env::var("TLS_KEY_PATH").unwrap_or_default(),
+      log_level: env::var("LOG_LEVEL").unwrap_or_default(),
+      log_targets: env::var("LOG_TARGETS").unwrap_or_default(),
+      log_file: env::var("LOG_FILE").unwrap_or_default(),
+      log_format: env::var("LOG_FORMAT").unwrap_or_default(),
+      debug_dump_dir: env::var("DEBUG_DUMP_DIR").unwrap_or_default(),
+      debug_dump_max_files: env::var("DEBUG_DUMP_MAX_FILES")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_DUMP_MAX_FILES),
+      debug_dump_max_bytes: env::var("DEBUG_DUMP_MAX_BYTES")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_DUMP_MAX_BYTES),
+      audit_schema: env::var("AUDIT_SCHEMA")
+        .map(|on| on == "1" || on.eq_ignore_ascii_case("true"))
+        .unwrap_or(false),
+    }
+  }
+
+  /// Turns on dumping of API responses and the schema audit, if they're
+  /// configured.
+  pub fn apply_debug_options(&self) {
+    if !self.debug_dump_dir.is_empty() {
+      destiny::dump_responses(
+        PathBuf::from(&self.debug_dump_dir),
+        self.debug_dump_max_files,
+        self.debug_dump_max_bytes,
+      );
+    }
+    if self.audit_schema {
+      destiny::audit_schema();
+    }
+  }
+
+  pub fn wishlist(&self) -> Option<String> {
+    if self.wishlist_path.is_empty() {
+      None
+    } else {
+      Some(self.wishlist_path.clone())
+    }
+  }
+
+  /// What's missing or malformed for serving the web app, if anything.
+  pub fn problems(&self) -> Vec<String> {
+    let mut problems = [
+      ("CANONICAL_URL", &self.canonical_url),
+      ("OAUTH_PATH", &self.oauth_path),
+      ("API_KEY", &self.api_key),
+      ("CLIENT_ID", &self.client_id),
+      ("CLIENT_SECRET", &self.client_secret),
+    ].iter()
+      .filter(|&&(_, value)| value.is_empty())
+      .map(|&(name, _)| format!("{} is not set", name))
+      .collect::<Vec<_>>();
+    if let Err(e) = self.oauth_url() {
+      problems.push(e.to_string());
+    }
+    if let Err(e) = self.tls() {
+      problems.push(e.to_string());
+    }
+    if let Err(e) = self.profile_ttl() {
+      problems.push(e.to_string());
+    }
+    problems
+  }
+
+  /// How long to keep each user's profile: PROFILE_TTL seconds, or
+  /// DEFAULT_PROFILE_TTL if it isn't set.
+  pub fn profile_ttl(&self) -> Result<Duration> {
+    if self.profile_ttl.is_empty() {
+      return Ok(Duration::from_secs(DEFAULT_PROFILE_TTL));
+    }
+    let secs = self.profile_ttl.parse().map_err(|e| {
+      Problem::ConfigInvalid(format!("PROFILE_TTL {:?}: {}", self.profile_ttl, e))
+    })?;
+    Ok(Duration::from_secs(secs))
+  }
+
+  /// The certificate and key paths, if we're to serve HTTPS ourselves.
+  pub fn tls(&self) -> Result<Option<(String, String)>> {
+    match (self.tls_cert_path.is_empty(), self.tls_key_path.is_empty()) {
+      (true, true) => Ok(None),
+      (false, false) => Ok(Some((self.tls_cert_path.clone(), self.tls_key_path.clone()))),
+      _ => Err(Problem::ConfigInvalid("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_owned()).into()),
+    }
+  }
+
   pub fn oauth_url(&self) -> Result<Url> {
-    let url: Url = self.canonical_url.parse()?;
-    Ok(url.join(&self.oauth_path)?)
+    let url: Url = self.canonical_url.parse().map_err(|e| {
+      Problem::ConfigInvalid(format!("CANONICAL_URL {:?}: {}", self.canonical_url, e))
+    })?;
+    Ok(url.join(&self.oauth_path).map_err(|e| {
+      Problem::ConfigInvalid(format!("OAUTH_PATH {:?}: {}", self.oauth_path, e))
+    })?)
   }
 }
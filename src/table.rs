@@ -2,81 +2,461 @@ use std::cmp;
 use ::std::fmt;
 use std::rc::Rc;
 use std::iter::FromIterator;
+use serde_json;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Columns are never squeezed narrower than this when fitting a terminal.
+const MIN_FIT_WIDTH: usize = 4;
+const SEPARATOR: &str = " | ";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+  Text,
+  Csv,
+  Tsv,
+  Markdown,
+  JsonLines,
+}
+
+impl Format {
+  pub fn from_name(name: &str) -> Option<Format> {
+    match name {
+      "text" | "txt" => Some(Format::Text),
+      "csv" => Some(Format::Csv),
+      "tsv" => Some(Format::Tsv),
+      "markdown" | "md" => Some(Format::Markdown),
+      "jsonl" | "ndjson" => Some(Format::JsonLines),
+      _ => None,
+    }
+  }
+
+  pub fn from_mime_type(mime: &str) -> Option<Format> {
+    match mime {
+      "text/plain" => Some(Format::Text),
+      "text/csv" => Some(Format::Csv),
+      "text/tab-separated-values" => Some(Format::Tsv),
+      "text/markdown" => Some(Format::Markdown),
+      "application/x-ndjson" | "application/jsonl" => Some(Format::JsonLines),
+      _ => None,
+    }
+  }
+
+  pub fn mime_type(&self) -> &'static str {
+    match *self {
+      Format::Text => "text/plain; charset=utf-8",
+      Format::Csv => "text/csv; charset=utf-8",
+      Format::Tsv => "text/tab-separated-values; charset=utf-8",
+      Format::Markdown => "text/markdown; charset=utf-8",
+      Format::JsonLines => "application/x-ndjson",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+  Left,
+  Right,
+}
 
 #[derive(Clone)]
 struct Field<T> {
-  get_field: Rc<fn(&T) -> String>,
+  get_field: Rc<Fn(&T) -> String>,
+  aggregate: Option<Rc<Fn(&[T]) -> String>>,
   width: usize,
+  max_width: Option<usize>,
+  align: Align,
   name: String,
+  key: String,
 }
 
 impl<T> Field<T> {
   fn sample_width(&mut self, t: &T) {
-    self.width = cmp::max((self.get_field)(t).len(), self.width)
+    let width = cmp::max(UnicodeWidthStr::width((self.get_field)(t).as_str()), self.width);
+    self.width = self.max_width.map_or(width, |max| cmp::min(max, width))
   }
 
   fn format_name(&self) -> String {
-    format!("{1:0$}", self.width, self.name)
+    pad(&self.name, self.width, self.align)
   }
 
   fn format(&self, t: &T) -> String {
-    format!("{1:0$}", self.width, (self.get_field)(t))
+    pad(&(self.get_field)(t), self.width, self.align)
+  }
+
+  fn summarize(&self, ts: &[T]) -> String {
+    self.aggregate.as_ref().map_or("".to_owned(), |aggregate| aggregate(ts))
+  }
+}
+
+// Pads or truncates `value` to exactly `width` terminal columns.
+fn pad(value: &str, width: usize, align: Align) -> String {
+  let value = truncate(value, width);
+  let padding = " ".repeat(width - UnicodeWidthStr::width(value.as_str()));
+  match align {
+    Align::Left => value + &padding,
+    Align::Right => padding + &value,
   }
 }
 
+fn truncate(value: &str, width: usize) -> String {
+  if UnicodeWidthStr::width(value) <= width {
+    return value.to_owned();
+  }
+  let mut out = String::new();
+  let mut used = 0;
+  for c in value.chars() {
+    let w = UnicodeWidthChar::width(c).unwrap_or(0);
+    if used + w + 1 > width {
+      break;
+    }
+    out.push(c);
+    used += w;
+  }
+  if width > 0 {
+    out.push('…');
+  }
+  out
+}
+
 #[derive(Clone)]
 pub struct Printer<T> {
   fields: Vec<Field<T>>,
-  each_row: Rc<fn(&T)>,
+  each_row: Vec<Rc<Fn(&T)>>,
+  group_by: Option<Rc<Fn(&T) -> String>>,
 }
 
 pub fn printer<T>() -> Printer<T> {
   Printer {
     fields: Vec::new(),
-    each_row: Rc::new(|_| ()),
+    each_row: Vec::new(),
+    group_by: None,
   }
 }
 
 impl<T> Printer<T>
   where T: Clone
 {
-  pub fn field(mut self, name: &str, get_field: fn(&T) -> String) -> Printer<T> {
+  pub fn field<F>(self, name: &str, get_field: F) -> Printer<T>
+    where F: Fn(&T) -> String + 'static
+  {
+    let key = name.to_lowercase().replace(' ', "_");
+    self.keyed_field(&key, name, get_field)
+  }
+
+  /// Like `field`, but with an explicit key for formats that label each
+  /// value, like JSON Lines.
+  pub fn keyed_field<F>(mut self, key: &str, name: &str, get_field: F) -> Printer<T>
+    where F: Fn(&T) -> String + 'static
+  {
     self.fields.push(Field {
       name: name.to_owned(),
+      key: key.to_owned(),
       get_field: Rc::new(get_field),
-      width: name.len(),
+      aggregate: None,
+      width: UnicodeWidthStr::width(name),
+      max_width: None,
+      align: Align::Left,
     });
     self
   }
 
-  pub fn each_row<F>(&self, f: fn(&T)) -> Self {
-    Printer {
-      fields: self.fields.clone(),
-      each_row: Rc::new(f),
+  /// Aligns the most recently added field, e.g. to right-align numbers.
+  pub fn align(mut self, align: Align) -> Printer<T> {
+    if let Some(field) = self.fields.last_mut() {
+      field.align = align;
+    }
+    self
+  }
+
+  /// Truncates the most recently added field's text output to `width`
+  /// columns, ending it with an ellipsis.
+  pub fn max_width(mut self, width: usize) -> Printer<T> {
+    if let Some(field) = self.fields.last_mut() {
+      field.max_width = Some(width);
+      field.width = cmp::min(field.width, width);
+    }
+    self
+  }
+
+  /// Summarizes the most recently added field's column in the footer, e.g.
+  /// with a count or a maximum. Grouped tables get a footer per group too.
+  pub fn aggregate<F>(mut self, aggregate: F) -> Printer<T>
+    where F: Fn(&[T]) -> String + 'static
+  {
+    if let Some(field) = self.fields.last_mut() {
+      field.aggregate = Some(Rc::new(aggregate));
     }
+    self
+  }
+
+  /// Starts a new group, with its own header, wherever this key changes
+  /// between consecutive rows. Sort by the same key to keep groups whole.
+  pub fn group_by<F>(mut self, key: F) -> Printer<T>
+    where F: Fn(&T) -> String + 'static
+  {
+    self.group_by = Some(Rc::new(key));
+    self
+  }
+
+  /// Runs `f` on each row as it's added to a table.
+  pub fn each_row<F>(mut self, f: F) -> Printer<T>
+    where F: Fn(&T) + 'static
+  {
+    self.each_row.push(Rc::new(f));
+    self
   }
 
   pub fn with_items<U>(&mut self, ts: U) -> Table<T>
-    where T: Clone,
-          U: IntoIterator<Item = T> + Clone
+    where U: IntoIterator<Item = T>
   {
     let mut np = self.clone();
-    for t in ts.clone() {
+    let items = Vec::from_iter(ts);
+    for t in items.iter() {
+      for hook in np.each_row.iter() {
+        hook(t)
+      }
       for f in np.fields.iter_mut() {
-        f.sample_width(&t)
+        f.sample_width(t)
+      }
+    }
+
+    let groups = np.groups(&items);
+    let footer = np.footer(&items);
+    for row in groups.iter().filter_map(|g| g.footer.as_ref()).chain(footer.iter()) {
+      for (f, value) in np.fields.iter_mut().zip(row.iter()) {
+        f.width = cmp::max(f.width, UnicodeWidthStr::width(value.as_str()));
       }
     }
 
     return Table {
       printer: np,
-      items: Vec::from_iter(ts),
+      items: items,
+      groups: groups,
+      footer: footer,
+    };
+  }
+
+  fn groups(&self, items: &[T]) -> Vec<Group> {
+    let key = match self.group_by {
+      Some(ref key) => key,
+      None => {
+        return vec![Group {
+                      title: None,
+                      start: 0,
+                      end: items.len(),
+                      footer: None,
+                    }]
+      }
     };
+
+    let mut groups: Vec<Group> = Vec::new();
+    for (i, t) in items.iter().enumerate() {
+      let title = key(t);
+      let continues = groups.last().map_or(false, |g| g.title.as_ref() == Some(&title));
+      if continues {
+        if let Some(group) = groups.last_mut() {
+          group.end = i + 1;
+        }
+      } else {
+        groups.push(Group {
+          title: Some(title),
+          start: i,
+          end: i + 1,
+          footer: None,
+        });
+      }
+    }
+    for group in groups.iter_mut() {
+      group.footer = self.footer(&items[group.start..group.end]);
+    }
+    groups
+  }
+
+  fn footer(&self, items: &[T]) -> Option<Vec<String>> {
+    if self.fields.iter().any(|f| f.aggregate.is_some()) {
+      Some(self.fields.iter().map(|f| f.summarize(items)).collect())
+    } else {
+      None
+    }
+  }
+}
+
+impl<T> Printer<T> {
+  /// Sizes columns from `sample`, which needn't be every item, and returns
+  /// a renderer for rows as they become available. Values wider than the
+  /// sample's are truncated, and there are no groups or footers.
+  pub fn stream(&self, sample: &[T], format: Format) -> RowStream<T>
+    where T: Clone
+  {
+    let mut np = self.clone();
+    for t in sample {
+      for f in np.fields.iter_mut() {
+        f.sample_width(t)
+      }
+    }
+    RowStream {
+      printer: np,
+      format: format,
+    }
+  }
+
+  fn names(&self) -> Vec<String> {
+    self.fields.iter().map(|f| f.name.clone()).collect()
+  }
+
+  fn values(&self, t: &T) -> Vec<String> {
+    self.fields.iter().map(|f| (f.get_field)(t)).collect()
+  }
+
+  // Whatever precedes the rows in `format`.
+  fn header(&self, format: Format) -> String {
+    match format {
+      Format::Text => {
+        let line = self.fields.iter().map(|f| f.format_name()).collect::<Vec<_>>().join(SEPARATOR);
+        format!("{}\n", line)
+      }
+      Format::Csv => delimited_row(self.names(), ",", csv_quote),
+      Format::Tsv => delimited_row(self.names(), "\t", tsv_clean),
+      Format::Markdown => {
+        markdown_row(self.names()) +
+        &markdown_row(self.fields.iter().map(|_| "---".to_owned()).collect())
+      }
+      Format::JsonLines => String::new(),
+    }
+  }
+
+  fn row(&self, t: &T, format: Format) -> String {
+    match format {
+      Format::Text => {
+        let line = self.fields.iter().map(|f| f.format(t)).collect::<Vec<_>>().join(SEPARATOR);
+        format!("{}\n", line)
+      }
+      Format::Csv => delimited_row(self.values(t), ",", csv_quote),
+      Format::Tsv => delimited_row(self.values(t), "\t", tsv_clean),
+      Format::Markdown => markdown_row(self.values(t)),
+      Format::JsonLines => {
+        let pairs = self.fields.iter().map(|f| {
+          format!(
+            "{}:{}",
+            serde_json::Value::String(f.key.clone()),
+            serde_json::Value::String((f.get_field)(t))
+          )
+        });
+        format!("{{{}}}\n", pairs.collect::<Vec<_>>().join(","))
+      }
+    }
+  }
+}
+
+pub struct RowStream<T> {
+  printer: Printer<T>,
+  format: Format,
+}
+
+impl<T> RowStream<T> {
+  pub fn header(&self) -> String {
+    self.printer.header(self.format)
   }
+
+  pub fn row(&self, t: &T) -> String {
+    for hook in self.printer.each_row.iter() {
+      hook(t)
+    }
+    self.printer.row(t, self.format)
+  }
+}
+
+struct Group {
+  title: Option<String>,
+  start: usize,
+  end: usize,
+  footer: Option<Vec<String>>,
 }
 
+/// A printer with its items. Group headers and footers only appear in the
+/// text rendering; the other formats stay plain rows of data.
 pub struct Table<T> {
   printer: Printer<T>,
   items: Vec<T>,
+  groups: Vec<Group>,
+  footer: Option<Vec<String>>,
+}
+
+impl<T> Table<T> {
+  /// Narrows the widest columns until a text row fits in `width` terminal
+  /// columns, or every column is as narrow as we'll go.
+  pub fn fit_to_width(&mut self, width: usize) {
+    let fields = &mut self.printer.fields;
+    let separators = SEPARATOR.len() * fields.len().saturating_sub(1);
+    let mut total = fields.iter().map(|f| f.width).sum::<usize>() + separators;
+
+    while total > width {
+      match fields.iter_mut().filter(|f| f.width > MIN_FIT_WIDTH).max_by_key(|f| f.width) {
+        Some(widest) => {
+          widest.width -= 1;
+          total -= 1;
+        }
+        None => break,
+      }
+    }
+  }
+
+  pub fn render(&self, format: Format) -> String {
+    match format {
+      Format::Text => format!("{}", self),
+      _ => {
+        let mut out = self.printer.header(format);
+        for t in self.items.iter() {
+          out.push_str(&self.printer.row(t, format));
+        }
+        out
+      }
+    }
+  }
+}
+
+fn delimited_row(values: Vec<String>, separator: &str, clean: fn(String) -> String) -> String {
+  format!("{}\n", values.into_iter().map(clean).collect::<Vec<_>>().join(separator))
+}
+
+fn csv_quote(value: String) -> String {
+  if value.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value
+  }
+}
+
+// TSV has no quoting, so anything that would break a row becomes a space.
+fn tsv_clean(value: String) -> String {
+  value.replace(|c: char| c == '\t' || c == '\n' || c == '\r', " ")
+}
+
+fn markdown_row(values: Vec<String>) -> String {
+  let cells = values
+    .iter()
+    .map(|v| v.replace('|', "\\|").replace('\n', " "))
+    .collect::<Vec<_>>();
+  format!("| {} |\n", cells.join(" | "))
+}
+
+impl<T> Table<T> {
+  fn format_footer(&self, values: &[String]) -> String {
+    self
+      .printer
+      .fields
+      .iter()
+      .zip(values.iter())
+      .map(|(f, value)| pad(value, f.width, f.align))
+      .collect::<Vec<_>>()
+      .join(SEPARATOR)
+  }
+
+  fn rule(&self) -> String {
+    let width = self.printer.fields.iter().map(|f| f.width).sum::<usize>() +
+                SEPARATOR.len() * self.printer.fields.len().saturating_sub(1);
+    "-".repeat(width)
+  }
 }
 
 impl<T> fmt::Display for Table<T> {
@@ -84,16 +464,80 @@ impl<T> fmt::Display for Table<T> {
     if self.items.len() == 0 {
       return Ok(());
     }
-    let line: String =
-      self.printer.fields.iter().map(|f| f.format_name()).collect::<Vec<_>>().join(" | ");
-    write!(f, "{}\n", line)?;
+    write!(f, "{}", self.printer.header(Format::Text))?;
+
+    for group in self.groups.iter() {
+      if let Some(ref title) = group.title {
+        write!(f, "\n{}\n", title)?;
+      }
+      for t in self.items[group.start..group.end].iter() {
+        write!(f, "{}", self.printer.row(t, Format::Text))?;
+      }
+      if let Some(ref footer) = group.footer {
+        write!(f, "{}\n", self.format_footer(footer))?;
+      }
+    }
 
-    for t in self.items.iter() {
-      let line: String =
-        self.printer.fields.iter().map(|f| f.format(&t)).collect::<Vec<_>>().join(" | ");
-      write!(f, "{}\n", line)?;
-      (self.printer.each_row)(t);
+    if let Some(ref footer) = self.footer {
+      write!(f, "{}\n{}\n", self.rule(), self.format_footer(footer))?;
     }
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn table(values: Vec<(&str, &str)>) -> Table<(String, String)> {
+    printer()
+      .field("Name", |v: &(String, String)| v.0.clone())
+      .field("Note", |v: &(String, String)| v.1.clone())
+      .with_items(values.into_iter().map(|(a, b)| (a.to_owned(), b.to_owned())))
+  }
+
+  #[test]
+  fn csv_quotes_only_when_needed() {
+    let t = table(vec![("plain", "a, b"), ("say \"hi\"", "two\nlines")]);
+    assert_eq!(
+      t.render(Format::Csv),
+      "Name,Note\nplain,\"a, b\"\n\"say \"\"hi\"\"\",\"two\nlines\"\n"
+    );
+  }
+
+  #[test]
+  fn tsv_replaces_tabs_and_newlines() {
+    let t = table(vec![("a\tb", "c\r\nd")]);
+    assert_eq!(t.render(Format::Tsv), "Name\tNote\na b\tc  d\n");
+  }
+
+  #[test]
+  fn markdown_escapes_pipes() {
+    let t = table(vec![("a|b", "c\nd")]);
+    assert_eq!(t.render(Format::Markdown), "| Name | Note |\n| --- | --- |\n| a\\|b | c d |\n");
+  }
+
+  #[test]
+  fn truncates_with_an_ellipsis() {
+    assert_eq!(truncate("short", 5), "short");
+    assert_eq!(truncate("too long", 5), "too …");
+    assert_eq!(truncate("anything", 0), "");
+  }
+
+  #[test]
+  fn pads_by_display_width() {
+    assert_eq!(pad("ab", 4, Align::Left), "ab  ");
+    assert_eq!(pad("ab", 4, Align::Right), "  ab");
+    assert_eq!(pad("日本語", 4, Align::Left), "日… ");
+    assert_eq!(pad("日本", 4, Align::Right), "日本");
+  }
+
+  #[test]
+  fn max_width_truncates_text_columns() {
+    let t = printer()
+      .field("Name", |v: &String| v.clone())
+      .max_width(6)
+      .with_items(vec!["Gjallarhorn".to_owned()]);
+    assert_eq!(t.render(Format::Text).lines().nth(1), Some("Gjall…"));
+  }
+}